@@ -16,19 +16,35 @@ func _ready():
 	var playerIdNode = get_node("Panel/PlayerId")
 	playerIdNode.text = "Player ID: " + str(random_palyer_id)
 	
+	rust_node.peer_found.connect(_on_peer_found)
+	rust_node.peer_updated.connect(_on_peer_updated)
+	rust_node.peer_lost.connect(_on_peer_lost)
 	rust_node.start_discovery_service(random_palyer_id)
-
-	discover_loop(random_palyer_id)
 	
-	await rust_node.start_tic_tac_toe_server();
-	print("server started")
+	rust_node.server_started.connect(_on_server_started)
+	rust_node.server_failed.connect(_on_server_failed)
+	rust_node.start_tic_tac_toe_server()
+
+
+func _on_server_started(port: int) -> void:
+	print("server started on port %d" % port)
+
+
+func _on_server_failed(reason: String) -> void:
+	print("server failed: %s" % reason)
+
+
+func _on_peer_found(peer: Dictionary) -> void:
+	print("peer found: %s (%s)" % [peer.name, peer.ip])
+
+
+func _on_peer_updated(peer: Dictionary) -> void:
+	for room in peer.rooms:
+		print("%s is hosting room '%s' (%d/%d)" % [peer.name, room.room_id, room.players, room.capacity])
 
 
-func discover_loop(playerId) -> void:
-	while true:
-		var result = rust_node.discover_peers()
-		print("peers result for %s = %s" % [playerId, result])
-		await get_tree().create_timer(5.0).timeout
+func _on_peer_lost(peer: Dictionary) -> void:
+	print("peer lost: %s" % peer.name)
 
 
 func _on_exit_button_pressed():
@@ -0,0 +1,101 @@
+// Load benchmark: plays many concurrent games against an in-process server over real WebSockets
+// and reports move throughput. Usage: `cargo bench --bench load -- [rooms] [games_per_room]`
+use futures_util::{SinkExt, StreamExt};
+use rust_tic_tac_toe_game_server::server::{serve, RateLimit, ServerConfig, PROTOCOL_VERSION};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// x wins along the top row in five moves
+const MOVES: [(u8, u8); 5] = [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)];
+
+async fn wait_for(ws: &mut Ws, response_type: &str, matches: impl Fn(&Value) -> bool) {
+    while let Some(Ok(msg)) = ws.next().await {
+        if let Message::Text(text) = msg {
+            let v: Value = serde_json::from_str(&text).unwrap();
+            if v["response_type"] == response_type && matches(&v["response"]) { return; }
+        }
+    }
+    panic!("connection closed while waiting for {response_type}");
+}
+
+async fn send(ws: &mut Ws, payload: Value) {
+    ws.send(Message::Text(payload.to_string().into())).await.unwrap();
+}
+
+async fn play_room(addr: SocketAddr, room_id: String, games: u64) -> u64 {
+    let url = format!("ws://{addr}/join/{room_id}?version={PROTOCOL_VERSION}");
+    let (mut x, _) = connect_async(&url).await.unwrap();
+    let (mut o, _) = connect_async(&url).await.unwrap();
+
+    // The second join auto-starts the first game
+    let started = |r: &Value| r["started"] == true && r["moves_count"] == 0;
+    wait_for(&mut x, "game_state", started).await;
+    wait_for(&mut o, "game_state", started).await;
+
+    let mut moves = 0;
+    for game in 0..games {
+        for (i, (mx, my)) in MOVES.iter().enumerate() {
+            let request_id = format!("{game}-{i}");
+            let mover = if i % 2 == 0 { &mut x } else { &mut o };
+            send(mover, json!({ "action": "make_move", "move_payload": { "x": mx, "y": my }, "request_id": request_id })).await;
+            let moves_count = i + 1;
+            let applied = move |r: &Value| r["moves_count"] == moves_count;
+            wait_for(&mut x, "move_made", applied).await;
+            wait_for(&mut o, "move_made", applied).await;
+            // The mover's ack follows its move_made
+            let mover = if i % 2 == 0 { &mut x } else { &mut o };
+            wait_for(mover, "ack", |r| r["request_id"] == request_id.as_str()).await;
+            moves += 1;
+        }
+        if game + 1 < games {
+            send(&mut x, json!({ "action": "restart_game" })).await;
+            wait_for(&mut x, "game_state", started).await;
+            wait_for(&mut o, "game_state", started).await;
+        }
+    }
+    moves
+}
+
+fn main() {
+    // `cargo bench` appends `--bench`; only positional arguments are ours
+    let args: Vec<String> = std::env::args().skip(1).filter(|a| !a.starts_with("--")).collect();
+    let rooms: usize = args.first().and_then(|a| a.parse().ok()).unwrap_or(200);
+    let games: u64 = args.get(1).and_then(|a| a.parse().ok()).unwrap_or(20);
+
+    let rt = tokio::runtime::Runtime::new().expect("Failed to create Tokio runtime");
+    rt.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Every simulated player connects from 127.0.0.1 and plays far faster than a human, so lift the rate limits
+        let unlimited = RateLimit { burst: u32::MAX, per_second: 1e9 };
+        let config = ServerConfig {
+            ip_join_limit: unlimited,
+            ip_action_limit: unlimited,
+            connection_action_limit: unlimited,
+            ..ServerConfig::default()
+        };
+        tokio::spawn(serve(listener, config));
+
+        let start = Instant::now();
+        let handles: Vec<_> = (0..rooms)
+            .map(|i| tokio::spawn(play_room(addr, format!("bench-{i}"), games)))
+            .collect();
+        let mut total_moves = 0;
+        for handle in handles {
+            total_moves += handle.await.unwrap();
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "{rooms} rooms x {games} games: {total_moves} moves in {:.2?} ({:.0} moves/s)",
+            elapsed,
+            total_moves as f64 / elapsed.as_secs_f64()
+        );
+    });
+}
@@ -0,0 +1,224 @@
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use axum::extract::{ConnectInfo, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use tracing::info;
+use crate::server::SharedState;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAX_DISPLAY_NAME_LEN: usize = 32;
+
+/// Verified identity of a player, taken from a session token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Player {
+    pub id: String,
+    pub display_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Claims {
+    player_id: String,
+    display_name: String,
+    // Expiry, unix seconds
+    exp: u64,
+}
+
+/// Issues and verifies session tokens: `base64url(claims JSON) "." base64url(HMAC-SHA256 of the first part)`.
+pub struct TokenSigner {
+    key: Vec<u8>,
+    ttl: Duration,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    getrandom::fill(&mut buf).expect("OS random number generator unavailable");
+    buf
+}
+
+impl TokenSigner {
+    // Without a configured secret a random one is used, so tokens stop verifying when the process restarts.
+    pub fn new(secret: Option<&str>, ttl: Duration) -> Self {
+        let key = match secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => random_bytes::<32>().to_vec(),
+        };
+        Self { key, ttl }
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    // Returns the token and its expiry (unix seconds).
+    pub fn issue(&self, player: &Player) -> (String, u64) {
+        let exp = unix_now() + self.ttl.as_secs();
+        let claims = Claims { player_id: player.id.clone(), display_name: player.display_name.clone(), exp };
+        let body = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let mut mac = self.mac();
+        mac.update(body.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        (format!("{body}.{signature}"), exp)
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Player, &'static str> {
+        let (body, signature) = token.split_once('.').ok_or("invalid_token")?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| "invalid_token")?;
+        let mut mac = self.mac();
+        mac.update(body.as_bytes());
+        // Constant-time comparison
+        mac.verify_slice(&signature).map_err(|_| "invalid_token")?;
+        let claims = URL_SAFE_NO_PAD.decode(body).map_err(|_| "invalid_token")?;
+        let claims: Claims = serde_json::from_slice(&claims).map_err(|_| "invalid_token")?;
+        if claims.exp <= unix_now() {
+            return Err("token_expired");
+        }
+        Ok(Player { id: claims.player_id, display_name: claims.display_name })
+    }
+}
+
+// Token from `Authorization: Bearer <token>`, used by clients that can set headers (browsers can't on WebSockets).
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers.get(header::AUTHORIZATION)?.to_str().ok()?.strip_prefix("Bearer ")
+}
+
+fn auth_error(status: StatusCode, code: &str) -> Response {
+    (status, Json(json!({ "code": code }))).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct GuestRequest {
+    pub display_name: String,
+}
+
+#[derive(Serialize)]
+pub struct GuestResponse {
+    pub token: String,
+    pub player_id: String,
+    pub display_name: String,
+    pub expires_at: u64,
+}
+
+// Issue a guest identity. Presenting a still-valid token keeps its player id, so a client can renew its token
+// (or change its display name) without losing its seats.
+pub async fn guest_login(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(request): Json<GuestRequest>,
+) -> Response {
+    if !state.ip_join_limiter.try_take(addr.ip()) {
+        state.metrics.errors.with_label_values(&["rate_limited"]).inc();
+        return auth_error(StatusCode::TOO_MANY_REQUESTS, "rate_limited");
+    }
+    let display_name = request.display_name.trim();
+    if display_name.is_empty() || display_name.chars().count() > MAX_DISPLAY_NAME_LEN {
+        return auth_error(StatusCode::BAD_REQUEST, "invalid_display_name");
+    }
+
+    let id = match bearer_token(&headers).map(|token| state.tokens.verify(token)) {
+        Some(Ok(previous)) => previous.id,
+        _ => random_bytes::<16>().iter().map(|b| format!("{b:02x}")).collect(),
+    };
+    let player = Player { id, display_name: display_name.to_string() };
+    let (token, expires_at) = state.tokens.issue(&player);
+    info!(player_id = %player.id, display_name = %player.display_name, "guest token issued");
+    Json(GuestResponse { token, player_id: player.id, display_name: player.display_name, expires_at }).into_response()
+}
+
+// Resolve the joining player's identity before the WebSocket upgrade. `Ok(None)` is an anonymous player,
+// only allowed when the server doesn't require auth.
+pub fn authenticate(state: &SharedState, query_token: Option<&str>, headers: &HeaderMap) -> Result<Option<Player>, &'static str> {
+    let result = match query_token.or_else(|| bearer_token(headers)) {
+        Some(token) => state.tokens.verify(token).map(Some),
+        None if state.config.require_auth => Err("auth_required"),
+        None => Ok(None),
+    };
+    if let Err(code) = result {
+        state.metrics.errors.with_label_values(&[code]).inc();
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer(secret: &str) -> TokenSigner {
+        TokenSigner::new(Some(secret), Duration::from_secs(3600))
+    }
+
+    fn player() -> Player {
+        Player { id: "p1".into(), display_name: "Alice".into() }
+    }
+
+    // Swap the first character of `part` for another base64url character, changing its leading bits
+    fn flip_first(part: &str) -> String {
+        let first = if part.starts_with('A') { 'B' } else { 'A' };
+        format!("{first}{}", &part[1..])
+    }
+
+    #[test]
+    fn issued_token_verifies() {
+        let signer = signer("secret");
+        let (token, exp) = signer.issue(&player());
+        assert!(exp > unix_now());
+        assert_eq!(signer.verify(&token), Ok(player()));
+    }
+
+    #[test]
+    fn tampered_body_is_rejected() {
+        let signer = signer("secret");
+        let (token, _) = signer.issue(&player());
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = Claims { player_id: "p2".into(), display_name: "Mallory".into(), exp: unix_now() + 3600 };
+        let body = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert_eq!(signer.verify(&format!("{body}.{signature}")), Err("invalid_token"));
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let signer = signer("secret");
+        let (token, _) = signer.issue(&player());
+        let (body, signature) = token.split_once('.').unwrap();
+        assert_eq!(signer.verify(&format!("{body}.{}", flip_first(signature))), Err("invalid_token"));
+    }
+
+    #[test]
+    fn token_from_another_secret_is_rejected() {
+        let (token, _) = signer("other secret").issue(&player());
+        assert_eq!(signer("secret").verify(&token), Err("invalid_token"));
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let signer = TokenSigner::new(Some("secret"), Duration::ZERO);
+        let (token, _) = signer.issue(&player());
+        assert_eq!(signer.verify(&token), Err("token_expired"));
+    }
+
+    #[test]
+    fn malformed_token_is_rejected() {
+        let signer = signer("secret");
+        assert_eq!(signer.verify(""), Err("invalid_token"));
+        assert_eq!(signer.verify("no-separator"), Err("invalid_token"));
+        assert_eq!(signer.verify("body.not*base64"), Err("invalid_token"));
+        // Correctly signed, but the body isn't base64url claims
+        let mut mac = signer.mac();
+        mac.update(b"not*base64");
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        assert_eq!(signer.verify(&format!("not*base64.{signature}")), Err("invalid_token"));
+    }
+}
@@ -0,0 +1,6 @@
+mod session;
+mod node;
+
+pub use session::{ClientEvent, ClientSession};
+#[cfg(feature = "server")]
+pub(crate) use node::{LocalEvent, LocalLink, TicTacToeClient};
@@ -0,0 +1,323 @@
+use godot::classes::web_socket_peer::State;
+use godot::classes::{INode, Node, WebSocketPeer};
+use godot::global::Error;
+use godot::prelude::*;
+#[cfg(feature = "server")]
+use tokio::sync::mpsc;
+use crate::client::{ClientEvent, ClientSession};
+use crate::room::{GameStateResponse, LatencyResponse, RoomStateResponse};
+#[cfg(feature = "server")]
+use crate::room::{Payload, RoomResponse};
+
+/// What the host's side of a local seat reports, see `RustNode.join_hosted_room`.
+#[cfg(feature = "server")]
+pub(crate) enum LocalEvent {
+    // The seat is being set up, like a socket that just opened
+    Opened,
+    Message(RoomResponse),
+    Closed,
+}
+
+/// A seat in a room of this process's own server: requests go in, room messages come out, no socket involved.
+/// Dropping it gives up the seat.
+#[cfg(feature = "server")]
+pub(crate) struct LocalLink {
+    pub commands: mpsc::UnboundedSender<Payload>,
+    pub events: mpsc::UnboundedReceiver<LocalEvent>,
+}
+
+enum Transport {
+    Socket(Gd<WebSocketPeer>),
+    #[cfg(feature = "server")]
+    Local(LocalLink),
+}
+
+/// Connects to a tic tac toe server over Godot's `WebSocketPeer` (so it works in web exports too) and reports
+/// the room through signals. Add it to the scene tree: the socket is polled every frame. The hosting instance can
+/// seat it in its own server without a socket through `RustNode.join_hosted_room`, with the same signals.
+#[derive(GodotClass)]
+#[class(init, base=Node)]
+pub(crate) struct TicTacToeClient {
+    // Session token from `/auth/guest`, sent with the next `join_room`; empty joins anonymously
+    #[var]
+    auth_token: GString,
+    transport: Option<Transport>,
+    session: ClientSession,
+    // Whether `connected` was emitted for the current transport
+    open: bool,
+    base: Base<Node>,
+}
+
+// Missing values (no winner, no current turn, empty cells) are empty strings, which GDScript tests as falsy.
+fn gstring(value: Option<&str>) -> GString {
+    GString::from(value.unwrap_or(""))
+}
+
+fn room_state_dict(room: &RoomStateResponse) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("room_id", gstring(Some(&room.room_id)));
+    dict.set("num_connections", room.num_connections as i64);
+    dict.set("message", gstring(Some(&room.message)));
+    dict.set("success", room.success);
+    dict.set("my_mark", gstring(Some(&room.my_mark)));
+    dict.set("protocol_version", room.protocol_version as i64);
+    dict
+}
+
+fn game_state_dict(game: &GameStateResponse) -> Dictionary {
+    // board[y][x], like on the wire
+    let board: VariantArray = game.board.iter()
+        .map(|row| row.iter().map(|cell| gstring(cell.as_deref())).collect::<Array<GString>>().to_variant())
+        .collect();
+    let mut dict = Dictionary::new();
+    dict.set("room_id", gstring(Some(&game.room_id)));
+    dict.set("board", board);
+    dict.set("current_turn", gstring(game.current_turn.as_deref()));
+    dict.set("winner", gstring(game.winner.as_deref()));
+    dict.set("started", game.started);
+    dict.set("moves_count", game.moves_count as i64);
+    dict.set("version", game.version as i64);
+    dict
+}
+
+// Mark -> round-trip time in milliseconds, -1 while not measured yet.
+fn latency_dict(latency: &LatencyResponse) -> Dictionary {
+    let mut dict = Dictionary::new();
+    for player in &latency.players {
+        dict.set(gstring(Some(&player.my_mark)), player.latency_ms.map_or(-1, |ms| ms as i64));
+    }
+    dict
+}
+
+#[godot_api]
+impl INode for TicTacToeClient {
+    fn process(&mut self, _delta: f64) {
+        self.poll();
+    }
+
+    fn exit_tree(&mut self) {
+        self.leave_room();
+    }
+}
+
+#[godot_api]
+impl TicTacToeClient {
+    #[signal]
+    fn connected();
+    // Emitted when the server closes the connection or it drops, not after `leave_room`
+    #[signal]
+    fn disconnected(code: i64, reason: GString);
+    #[signal]
+    fn room_state_changed(state: Dictionary);
+    // Always the full, current state, also after a single move
+    #[signal]
+    fn game_state_changed(state: Dictionary);
+    #[signal]
+    fn move_made(x: i64, y: i64, mark: GString);
+    #[signal]
+    fn latency_updated(latencies: Dictionary);
+    #[signal]
+    fn notice_received(message: GString);
+    #[signal]
+    fn error_received(code: GString, message: GString);
+
+    // Join `room_id` on `server_url` (e.g. `ws://192.168.1.20:3000`), leaving the current room first.
+    // False if the connection couldn't even be started; otherwise wait for `connected` / `room_state_changed`.
+    #[func]
+    fn join_room(&mut self, server_url: GString, room_id: GString) -> bool {
+        self.leave_room();
+        let token = self.auth_token.to_string();
+        let path = ClientSession::join_path(&room_id.to_string(), Some(token.as_str()).filter(|t| !t.is_empty()));
+        let url = format!("{}{}", server_url.to_string().trim_end_matches('/'), path);
+        let mut socket = WebSocketPeer::new_gd();
+        let result = socket.connect_to_url(url.as_str());
+        if result != Error::OK {
+            godot_error!("TicTacToeClient: cannot connect to {}: {:?}", url, result);
+            return false;
+        }
+        self.transport = Some(Transport::Socket(socket));
+        true
+    }
+
+    #[func]
+    fn leave_room(&mut self) {
+        // Dropping a local link gives up its seat
+        if let Some(Transport::Socket(mut socket)) = self.transport.take() {
+            socket.close();
+        }
+        self.session = ClientSession::new();
+        self.open = false;
+    }
+
+    #[func]
+    fn is_in_room(&self) -> bool {
+        self.open
+    }
+
+    #[func]
+    fn start_game(&mut self) -> bool {
+        let message = self.session.start_game();
+        self.send(message)
+    }
+
+    #[func]
+    fn make_move(&mut self, x: i64, y: i64) -> bool {
+        // Cells past 2 still go out so the server answers with `out_of_bounds`
+        let (Ok(x), Ok(y)) = (u8::try_from(x), u8::try_from(y)) else { return false; };
+        let message = self.session.make_move(x, y);
+        self.send(message)
+    }
+
+    #[func]
+    fn restart_game(&mut self) -> bool {
+        let message = self.session.restart_game();
+        self.send(message)
+    }
+
+    // Our mark (`x` or `o`), empty until seated.
+    #[func]
+    fn get_my_mark(&self) -> GString {
+        gstring(self.session.my_mark())
+    }
+
+    #[func]
+    fn is_my_turn(&self) -> bool {
+        self.session.is_my_turn()
+    }
+
+    // Last `room_state_changed` / `game_state_changed` payload, empty before the first one.
+    #[func]
+    fn get_room_state(&self) -> Dictionary {
+        self.session.room_state().map(room_state_dict).unwrap_or_default()
+    }
+
+    #[func]
+    fn get_game_state(&self) -> Dictionary {
+        self.session.game().map(game_state_dict).unwrap_or_default()
+    }
+}
+
+impl TicTacToeClient {
+    // Leave the current room and take the seat behind `link` instead.
+    #[cfg(feature = "server")]
+    pub(crate) fn join_local(&mut self, link: LocalLink) {
+        self.leave_room();
+        self.transport = Some(Transport::Local(link));
+    }
+
+    // Token for the next join, None when anonymous.
+    #[cfg(feature = "server")]
+    pub(crate) fn token(&self) -> Option<String> {
+        Some(self.auth_token.to_string()).filter(|t| !t.is_empty())
+    }
+
+    // False when not connected or the transport refused the message.
+    fn send(&mut self, message: String) -> bool {
+        if !self.open {
+            return false;
+        }
+        match self.transport.as_mut() {
+            Some(Transport::Socket(socket)) => socket.send_text(message.as_str()) == Error::OK,
+            // Requests are built as JSON by the session; a local seat takes them decoded
+            #[cfg(feature = "server")]
+            Some(Transport::Local(link)) => match serde_json::from_str::<Payload>(&message) {
+                Ok(payload) => link.commands.send(payload).is_ok(),
+                Err(_) => false,
+            },
+            None => false,
+        }
+    }
+
+    fn poll(&mut self) {
+        match &self.transport {
+            Some(Transport::Socket(socket)) => {
+                let socket = socket.clone();
+                self.poll_socket(socket);
+            }
+            #[cfg(feature = "server")]
+            Some(Transport::Local(_)) => self.poll_local(),
+            None => {}
+        }
+    }
+
+    fn poll_socket(&mut self, mut socket: Gd<WebSocketPeer>) {
+        socket.poll();
+        let state = socket.get_ready_state();
+        if state == State::OPEN {
+            if !self.open {
+                self.open = true;
+                self.signals().connected().emit();
+            }
+            while socket.get_available_packet_count() > 0 {
+                let packet = socket.get_packet();
+                match self.session.handle_message(&String::from_utf8_lossy(packet.as_slice())) {
+                    Ok(events) => {
+                        for event in events { self.dispatch(event); }
+                    }
+                    Err(err) => godot_warn!("TicTacToeClient: ignoring undecodable message: {}", err),
+                }
+                // A signal handler may have left or switched rooms
+                if !matches!(&self.transport, Some(Transport::Socket(current)) if *current == socket) { return; }
+            }
+        } else if state == State::CLOSED {
+            let code = socket.get_close_code() as i64;
+            let reason = socket.get_close_reason();
+            self.closed(code, &reason);
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn poll_local(&mut self) {
+        loop {
+            // Looked up again every time: a signal handler may have left or switched rooms
+            let Some(Transport::Local(link)) = self.transport.as_mut() else { return; };
+            let event = match link.events.try_recv() {
+                Ok(event) => event,
+                Err(mpsc::error::TryRecvError::Empty) => return,
+                Err(mpsc::error::TryRecvError::Disconnected) => LocalEvent::Closed,
+            };
+            match event {
+                LocalEvent::Opened => {
+                    self.open = true;
+                    self.signals().connected().emit();
+                }
+                LocalEvent::Message(message) => match self.session.handle_response(message) {
+                    Ok(events) => {
+                        for event in events { self.dispatch(event); }
+                    }
+                    Err(err) => godot_warn!("TicTacToeClient: ignoring undecodable message: {}", err),
+                },
+                LocalEvent::Closed => {
+                    // 1000: normal closure, like a socket the server closed
+                    self.closed(1000, &GString::new());
+                    return;
+                }
+            }
+        }
+    }
+
+    fn closed(&mut self, code: i64, reason: &GString) {
+        self.transport = None;
+        self.open = false;
+        self.signals().disconnected().emit(code, reason);
+    }
+
+    fn dispatch(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::RoomState(room) => self.signals().room_state_changed().emit(&room_state_dict(&room)),
+            ClientEvent::GameState(game) => self.signals().game_state_changed().emit(&game_state_dict(&game)),
+            ClientEvent::MoveMade(made) => {
+                self.signals().move_made().emit(made.x as i64, made.y as i64, &gstring(Some(&made.mark)));
+            }
+            ClientEvent::Latency(latency) => self.signals().latency_updated().emit(&latency_dict(&latency)),
+            ClientEvent::Notice(notice) => self.signals().notice_received().emit(&gstring(Some(&notice.message))),
+            ClientEvent::Error(error) => {
+                self.signals().error_received().emit(&gstring(Some(&error.code)), &gstring(Some(&error.message)));
+            }
+            ClientEvent::OutOfSync => {
+                let message = self.session.resync();
+                self.send(message);
+            }
+        }
+    }
+}
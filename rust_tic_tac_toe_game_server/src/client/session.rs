@@ -0,0 +1,220 @@
+use crate::room::{
+    Action, ErrorResponse, GameStateResponse, LatencyResponse, MakeMovePayload, MoveMadeResponse, NoticeResponse, Payload,
+    ResponseType, RoomResponse, RoomStateResponse, PROTOCOL_VERSION,
+};
+
+/// Something the server told us, already decoded. `GameState` always carries the full, current state: deltas
+/// are applied to the last snapshot before it is reported.
+pub enum ClientEvent {
+    RoomState(RoomStateResponse),
+    GameState(GameStateResponse),
+    MoveMade(MoveMadeResponse),
+    Latency(LatencyResponse),
+    Notice(NoticeResponse),
+    Error(ErrorResponse),
+    // A delta didn't follow the last known version; the caller should send `resync()`
+    OutOfSync,
+}
+
+/// Transport-independent client side of the room protocol (JSON encoding): builds request messages and folds
+/// incoming messages into the current room and game state.
+#[derive(Default)]
+pub struct ClientSession {
+    room_state: Option<RoomStateResponse>,
+    game: Option<GameStateResponse>,
+}
+
+// Percent-encode everything but RFC 3986 unreserved characters, so any room id or token is a valid URL part.
+fn encode_component(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        _ => format!("%{b:02X}"),
+    }).collect()
+}
+
+impl ClientSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Path and query to join `room_id` at the protocol version this crate speaks, e.g. `/join/lobby?version=4`.
+    pub fn join_path(room_id: &str, token: Option<&str>) -> String {
+        let mut path = format!("/join/{}?version={PROTOCOL_VERSION}", encode_component(room_id));
+        if let Some(token) = token {
+            path.push_str(&format!("&token={}", encode_component(token)));
+        }
+        path
+    }
+
+    pub fn room_state(&self) -> Option<&RoomStateResponse> {
+        self.room_state.as_ref()
+    }
+
+    pub fn game(&self) -> Option<&GameStateResponse> {
+        self.game.as_ref()
+    }
+
+    // Our mark, once the server has seated us.
+    pub fn my_mark(&self) -> Option<&str> {
+        self.room_state.as_ref().filter(|r| r.success).map(|r| r.my_mark.as_str())
+    }
+
+    fn request(action: Action, move_payload: Option<MakeMovePayload>) -> String {
+        serde_json::to_string(&Payload { action, move_payload, request_id: None }).unwrap()
+    }
+
+    pub fn start_game(&self) -> String {
+        Self::request(Action::StartGame, None)
+    }
+
+    pub fn make_move(&self, x: u8, y: u8) -> String {
+        Self::request(Action::MakeMove, Some(MakeMovePayload { x, y }))
+    }
+
+    pub fn restart_game(&self) -> String {
+        Self::request(Action::RestartGame, None)
+    }
+
+    pub fn resync(&self) -> String {
+        Self::request(Action::Resync, None)
+    }
+
+    // Decode one server message and fold it into the session state. A message that doesn't decode (malformed,
+    // or a response type this crate doesn't know) is an error and leaves the state untouched.
+    pub fn handle_message(&mut self, text: &str) -> Result<Vec<ClientEvent>, serde_json::Error> {
+        self.handle_response(serde_json::from_str(text)?)
+    }
+
+    // Like `handle_message`, for a message that never was text, e.g. from a room of this process's own server.
+    pub fn handle_response(&mut self, message: RoomResponse) -> Result<Vec<ClientEvent>, serde_json::Error> {
+        let body = message.response;
+        let events = match message.response_type {
+            ResponseType::RoomState => {
+                let room_state: RoomStateResponse = serde_json::from_value(body)?;
+                self.room_state = Some(room_state.clone());
+                vec![ClientEvent::RoomState(room_state)]
+            }
+            ResponseType::GameState => {
+                let game: GameStateResponse = serde_json::from_value(body)?;
+                self.game = Some(game.clone());
+                vec![ClientEvent::GameState(game)]
+            }
+            ResponseType::MoveMade => self.apply_move(serde_json::from_value(body)?),
+            ResponseType::Latency => vec![ClientEvent::Latency(serde_json::from_value(body)?)],
+            ResponseType::Notice => vec![ClientEvent::Notice(serde_json::from_value(body)?)],
+            ResponseType::Error => vec![ClientEvent::Error(serde_json::from_value(body)?)],
+            ResponseType::Ack => Vec::new(),
+        };
+        Ok(events)
+    }
+
+    fn apply_move(&mut self, made: MoveMadeResponse) -> Vec<ClientEvent> {
+        let Some(game) = self.game.as_mut() else { return vec![ClientEvent::OutOfSync]; };
+        if made.version <= game.version {
+            // Already reflected in a snapshot we got later
+            return Vec::new();
+        }
+        if made.version != game.version + 1 {
+            return vec![ClientEvent::OutOfSync];
+        }
+        let Some(cell) = game.board.get_mut(made.y as usize).and_then(|row| row.get_mut(made.x as usize)) else {
+            return vec![ClientEvent::OutOfSync];
+        };
+        *cell = Some(made.mark.clone());
+        game.current_turn = made.current_turn.clone();
+        game.winner = made.winner.clone();
+        game.moves_count = made.moves_count;
+        game.version = made.version;
+        let game = game.clone();
+        vec![ClientEvent::MoveMade(made), ClientEvent::GameState(game)]
+    }
+
+    // Whether it's our turn in a running game.
+    pub fn is_my_turn(&self) -> bool {
+        match (self.my_mark(), self.game.as_ref().and_then(|g| g.current_turn.as_deref())) {
+            (Some(mine), Some(turn)) => mine == turn,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn game_state(version: u64) -> String {
+        json!({
+            "response_type": "game_state",
+            "response": {
+                "room_id": "r", "board": [[null, null, null], [null, null, null], [null, null, null]],
+                "current_turn": "x", "winner": null, "started": true, "moves_count": 0, "version": version,
+            },
+        })
+        .to_string()
+    }
+
+    fn move_made(version: u64, x: u8, y: u8, mark: &str, next: &str) -> String {
+        json!({
+            "response_type": "move_made",
+            "response": {
+                "room_id": "r", "x": x, "y": y, "mark": mark, "version": version,
+                "current_turn": next, "winner": null, "moves_count": version - 1,
+            },
+        })
+        .to_string()
+    }
+
+    // A session holding the game at version 1 with an empty board
+    fn started() -> ClientSession {
+        let mut session = ClientSession::new();
+        session.handle_message(&game_state(1)).unwrap();
+        session
+    }
+
+    #[test]
+    fn contiguous_move_is_applied_to_the_snapshot() {
+        let mut session = started();
+        let events = session.handle_message(&move_made(2, 1, 0, "x", "o")).unwrap();
+        assert!(matches!(events.as_slice(), [ClientEvent::MoveMade(_), ClientEvent::GameState(_)]));
+        let ClientEvent::GameState(game) = &events[1] else { unreachable!() };
+        assert_eq!(game.version, 2);
+        assert_eq!(game.board[0][1].as_deref(), Some("x"));
+        assert_eq!(game.current_turn.as_deref(), Some("o"));
+        assert_eq!(session.game().unwrap().version, 2);
+    }
+
+    #[test]
+    fn version_gap_asks_for_a_resync() {
+        let mut session = started();
+        let events = session.handle_message(&move_made(3, 1, 0, "x", "o")).unwrap();
+        assert!(matches!(events.as_slice(), [ClientEvent::OutOfSync]));
+        // The snapshot is left alone until the resync's game state arrives
+        assert_eq!(session.game().unwrap().version, 1);
+        assert_eq!(session.game().unwrap().board[0][1], None);
+    }
+
+    #[test]
+    fn move_before_any_snapshot_asks_for_a_resync() {
+        let mut session = ClientSession::new();
+        let events = session.handle_message(&move_made(2, 1, 0, "x", "o")).unwrap();
+        assert!(matches!(events.as_slice(), [ClientEvent::OutOfSync]));
+    }
+
+    #[test]
+    fn stale_move_is_ignored() {
+        let mut session = ClientSession::new();
+        session.handle_message(&game_state(5)).unwrap();
+        assert!(session.handle_message(&move_made(5, 1, 0, "x", "o")).unwrap().is_empty());
+        assert!(session.handle_message(&move_made(3, 1, 0, "x", "o")).unwrap().is_empty());
+        assert_eq!(session.game().unwrap().board[0][1], None);
+    }
+
+    #[test]
+    fn malformed_message_is_an_error() {
+        let mut session = started();
+        assert!(session.handle_message("not json").is_err());
+        assert!(session.handle_message(r#"{"response_type":"move_made","response":{}}"#).is_err());
+        assert_eq!(session.game().unwrap().version, 1);
+    }
+}
@@ -0,0 +1,141 @@
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::info;
+use crate::server::SharedState;
+
+// How long browsers may cache a preflight answer, in seconds.
+const PREFLIGHT_MAX_AGE: &str = "600";
+
+fn is_allowed(state: &SharedState, origin: &str) -> bool {
+    let origin = origin.trim_end_matches('/');
+    match &state.config.allowed_origins {
+        None => true,
+        Some(allowed) => allowed.iter().any(|a| a.trim_end_matches('/').eq_ignore_ascii_case(origin)),
+    }
+}
+
+// Origin check and CORS headers for every route, including the WebSocket upgrade (browsers don't apply CORS to
+// WebSockets, so the server has to refuse foreign pages itself). Requests without an `Origin` header come from
+// native clients such as the Godot desktop build and are always let through.
+pub async fn origin_policy(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let Some(origin) = request.headers().get(header::ORIGIN).cloned() else {
+        return next.run(request).await;
+    };
+    if !origin.to_str().is_ok_and(|o| is_allowed(&state, o)) {
+        state.metrics.errors.with_label_values(&["origin_not_allowed"]).inc();
+        info!(origin = ?origin, path = %request.uri().path(), "request rejected: origin not allowed");
+        return (StatusCode::FORBIDDEN, "origin_not_allowed").into_response();
+    }
+
+    let preflight = request.method() == Method::OPTIONS && request.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+    let mut response = if preflight { StatusCode::NO_CONTENT.into_response() } else { next.run(request).await };
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    if preflight {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST, PUT, DELETE"));
+        headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("authorization, content-type"));
+        headers.insert(header::ACCESS_CONTROL_MAX_AGE, HeaderValue::from_static(PREFLIGHT_MAX_AGE));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::routing::{get, post};
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+    use crate::auth;
+    use crate::server::{AppState, ServerConfig};
+
+    const GAME: &str = "https://game.example.com";
+
+    async fn send(allowed_origins: Option<&[&str]>, method: Method, path: &str, headers: &[(header::HeaderName, &str)]) -> Response {
+        let config = ServerConfig {
+            allowed_origins: allowed_origins.map(|origins| origins.iter().map(|o| o.to_string()).collect()),
+            ..ServerConfig::default()
+        };
+        let state = Arc::new(AppState::new(config));
+        let app = Router::new()
+            .route("/auth/guest", post(auth::guest_login))
+            .route("/healthz", get(|| async { "ok" }))
+            .layer(middleware::from_fn_with_state(state.clone(), origin_policy))
+            .with_state(state);
+        let body = if method == Method::POST { Body::from(r#"{"display_name":"alice"}"#) } else { Body::empty() };
+        let mut request = Request::builder().method(method).uri(path).header(header::CONTENT_TYPE, "application/json");
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let mut request = request.body(body).unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        app.oneshot(request).await.unwrap()
+    }
+
+    fn allow_origin(response: &Response) -> Option<&str> {
+        response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn listed_origins_are_let_through_with_cors_headers() {
+        let response = send(Some(&[GAME]), Method::POST, "/auth/guest", &[(header::ORIGIN, GAME)]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(allow_origin(&response), Some(GAME));
+        assert_eq!(response.headers().get(header::VARY).unwrap(), "Origin");
+    }
+
+    #[tokio::test]
+    async fn other_origins_are_forbidden() {
+        let response = send(Some(&[GAME]), Method::GET, "/healthz", &[(header::ORIGIN, "https://evil.example.com")]).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(allow_origin(&response), None);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"origin_not_allowed");
+        // Prefixes don't count
+        let response = send(Some(&[GAME]), Method::GET, "/healthz", &[(header::ORIGIN, "https://game.example.com.evil.net")]).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn trailing_slashes_and_case_are_ignored() {
+        let response = send(Some(&["https://game.example.com/"]), Method::GET, "/healthz", &[(header::ORIGIN, GAME)]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(Some(&[GAME]), Method::GET, "/healthz", &[(header::ORIGIN, "https://game.example.com/")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send(Some(&[GAME]), Method::GET, "/healthz", &[(header::ORIGIN, "HTTPS://Game.Example.com")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // The browser's spelling is echoed back
+        assert_eq!(allow_origin(&response), Some("HTTPS://Game.Example.com"));
+    }
+
+    #[tokio::test]
+    async fn requests_without_an_origin_are_always_accepted() {
+        let response = send(Some(&[GAME]), Method::GET, "/healthz", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(allow_origin(&response), None);
+        let response = send(None, Method::GET, "/healthz", &[(header::ORIGIN, "https://anywhere.example.com")]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn preflights_for_the_guest_login_are_answered() {
+        let preflight = [(header::ORIGIN, GAME), (header::ACCESS_CONTROL_REQUEST_METHOD, "POST")];
+        let response = send(Some(&[GAME]), Method::OPTIONS, "/auth/guest", &preflight).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(allow_origin(&response), Some(GAME));
+        let headers = response.headers();
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_METHODS).unwrap().to_str().unwrap().contains("POST"));
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap().to_str().unwrap().contains("authorization"));
+        assert_eq!(headers.get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), PREFLIGHT_MAX_AGE);
+
+        let foreign = [(header::ORIGIN, "https://evil.example.com"), (header::ACCESS_CONTROL_REQUEST_METHOD, "POST")];
+        let response = send(Some(&[GAME]), Method::OPTIONS, "/auth/guest", &foreign).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use rust_udp_multicast_test::multicast_service;
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tracing::{debug, info, warn};
+
+// How often the multicast service's peer list is checked, and a hosted server announced
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Multicast group and port hosted game servers are announced on, next to the multicast service's player
+/// announcements. The default TTL of 1 keeps them on the local network.
+pub const SERVER_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 42, 99);
+pub const SERVER_PORT: u16 = 42424;
+
+// A server missing this many announcements in a row is no longer advertised
+const SERVER_TIMEOUT: Duration = Duration::from_secs(7);
+const MAX_DATAGRAM_SIZE: usize = 2048;
+
+// `multicast_service` is process-wide: a new service waits until the previous one has stopped, so a late
+// `stop_service` can't take down its successor.
+static SERVICE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// One entry of `multicast_service::get_peers()`, a JSON array of the players it currently hears.
+#[derive(Deserialize)]
+struct AnnouncedPlayer {
+    name: String,
+    ip: String,
+}
+
+/// A room on an advertised game server.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AdvertisedRoom {
+    pub room_id: String,
+    pub players: usize,
+    pub capacity: usize,
+}
+
+impl AdvertisedRoom {
+    pub fn is_open(&self) -> bool {
+        self.players < self.capacity
+    }
+}
+
+/// The game server an instance hosts; connect to `ws://<peer ip>:<port>/join/{room_id}`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct HostedServer {
+    pub port: u16,
+    pub protocol_version: u32,
+    pub rooms: Vec<AdvertisedRoom>,
+}
+
+/// Snapshot of the hosted server, taken for every announcement so room counts stay current.
+pub type HostingSource = Arc<dyn Fn() -> HostedServer + Send + Sync>;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    // `name` is the host's player name, as announced by its multicast service
+    Announce { name: String, server: HostedServer },
+    // Sent when the host stops advertising, so peers drop the server right away instead of timing out
+    Withdraw { name: String },
+}
+
+/// Another instance seen on the LAN.
+#[derive(Clone, Debug)]
+pub struct Peer {
+    pub name: String,
+    pub ip: IpAddr,
+    // None when it doesn't announce a server
+    pub hosting: Option<HostedServer>,
+    // Last time the multicast service listed it
+    pub last_seen: Instant,
+}
+
+impl Peer {
+    fn key(&self) -> (String, IpAddr) {
+        (self.name.clone(), self.ip)
+    }
+}
+
+pub enum DiscoveryEvent {
+    PeerFound(Peer),
+    // A known peer started or stopped hosting, or a player joined or left one of its rooms
+    PeerUpdated(Peer),
+    // The peer stopped its discovery service or dropped off the multicast service's list
+    PeerLost(Peer),
+}
+
+/// Running discovery service. Dropping it stops the service.
+pub struct DiscoveryHandle {
+    hosting: Arc<Mutex<Option<HostingSource>>>,
+    hosting_changed: Arc<Notify>,
+    peers: Arc<Mutex<HashMap<(String, IpAddr), Peer>>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl DiscoveryHandle {
+    pub fn peers(&self) -> Vec<Peer> {
+        let mut peers: Vec<Peer> = self.peers.lock().unwrap().values().cloned().collect();
+        peers.sort_by(|a, b| a.name.cmp(&b.name));
+        peers
+    }
+
+    // Advertise the game server this instance hosts (or stop advertising it); peers hear about it right away.
+    pub fn set_hosting(&self, hosting: Option<HostingSource>) {
+        *self.hosting.lock().unwrap() = hosting;
+        self.hosting_changed.notify_one();
+    }
+}
+
+impl Drop for DiscoveryHandle {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+// Several instances on one machine (e.g. two Godot windows while testing) share the port.
+fn bind_multicast() -> io::Result<StdUdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SERVER_PORT).into())?;
+    socket.join_multicast_v4(&SERVER_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Bind the server announcement socket for announcing `name` through `multicast_service` and reporting the players
+/// it hears to `events`, along with the servers they host. Returns the handle and the service itself, to be spawned
+/// on a Tokio runtime; it runs until the handle is dropped or `stop` resolves, then withdraws our server and stops
+/// the multicast service.
+pub fn start<S>(
+    name: String,
+    events: mpsc::UnboundedSender<DiscoveryEvent>,
+    stop: S,
+) -> io::Result<(DiscoveryHandle, impl Future<Output = ()> + Send + 'static)>
+where
+    S: Future<Output = ()> + Send + 'static,
+{
+    let socket = bind_multicast()?;
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let hosting = Arc::new(Mutex::new(None));
+    let hosting_changed = Arc::new(Notify::new());
+    let peers = Arc::new(Mutex::new(HashMap::new()));
+    let handle = DiscoveryHandle {
+        hosting: hosting.clone(),
+        hosting_changed: hosting_changed.clone(),
+        peers: peers.clone(),
+        shutdown: Some(shutdown_tx),
+    };
+    let service = async move {
+        // Registering the socket needs the runtime the service runs on
+        let socket = match UdpSocket::from_std(socket) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!(%err, "discovery socket unusable");
+                return;
+            }
+        };
+        let service = Discovery {
+            socket,
+            name,
+            hosting,
+            hosting_changed,
+            advertising: false,
+            servers: HashMap::new(),
+            peers,
+            events,
+        };
+        service.run(shutdown_rx, stop).await;
+    };
+    Ok((handle, service))
+}
+
+struct Discovery {
+    socket: UdpSocket,
+    name: String,
+    hosting: Arc<Mutex<Option<HostingSource>>>,
+    hosting_changed: Arc<Notify>,
+    // Whether our last message announced a server, so stopping withdraws it
+    advertising: bool,
+    // Servers announced by peers, with when they were last heard
+    servers: HashMap<(String, IpAddr), (HostedServer, Instant)>,
+    peers: Arc<Mutex<HashMap<(String, IpAddr), Peer>>>,
+    events: mpsc::UnboundedSender<DiscoveryEvent>,
+}
+
+// Serialize a server announcement, leaving out rooms (full ones first) until it fits in one datagram.
+fn encode_announcement(name: String, mut server: HostedServer) -> Vec<u8> {
+    server.rooms.sort_by(|a, b| b.is_open().cmp(&a.is_open()).then_with(|| a.room_id.cmp(&b.room_id)));
+    let mut message = ServerMessage::Announce { name, server };
+    loop {
+        let datagram = serde_json::to_vec(&message).unwrap();
+        match &mut message {
+            ServerMessage::Announce { server, .. } if datagram.len() > MAX_DATAGRAM_SIZE && !server.rooms.is_empty() => {
+                server.rooms.pop();
+            }
+            _ => return datagram,
+        }
+    }
+}
+
+impl Discovery {
+    async fn run<S: Future<Output = ()>>(mut self, mut shutdown: oneshot::Receiver<()>, stop: S) {
+        tokio::pin!(stop);
+        let _service = SERVICE.lock().await;
+        tokio::spawn(multicast_service::start_service(self.name.clone()));
+        info!(name = %self.name, "discovery started");
+        let own_ip = multicast_service::get_local_ipv4_in_string();
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = &mut stop => break,
+                _ = poll.tick() => {
+                    self.announce().await;
+                    self.poll(&own_ip).await;
+                }
+                _ = self.hosting_changed.notified() => self.announce().await,
+                received = self.socket.recv_from(&mut buf) => match received {
+                    Ok((len, from)) => self.receive(&buf[..len], from),
+                    Err(err) => warn!(%err, "discovery receive failed"),
+                },
+            }
+        }
+        if self.advertising {
+            self.send(&serde_json::to_vec(&ServerMessage::Withdraw { name: self.name.clone() }).unwrap()).await;
+        }
+        multicast_service::stop_service().await;
+        info!("discovery stopped");
+    }
+
+    async fn announce(&mut self) {
+        // Take the snapshot outside the lock; the source may have to look at every room
+        let source = self.hosting.lock().unwrap().clone();
+        match source {
+            Some(source) => {
+                self.send(&encode_announcement(self.name.clone(), source())).await;
+                self.advertising = true;
+            }
+            None if self.advertising => {
+                self.send(&serde_json::to_vec(&ServerMessage::Withdraw { name: self.name.clone() }).unwrap()).await;
+                self.advertising = false;
+            }
+            None => {}
+        }
+    }
+
+    async fn send(&self, datagram: &[u8]) {
+        if let Err(err) = self.socket.send_to(datagram, SocketAddrV4::new(SERVER_GROUP, SERVER_PORT)).await {
+            debug!(%err, "server announcement not sent");
+        }
+    }
+
+    fn receive(&mut self, datagram: &[u8], from: SocketAddr) {
+        if !apply_message(&mut self.servers, datagram, from.ip(), Instant::now()) {
+            debug!(%from, "ignoring malformed server announcement");
+        }
+    }
+
+    async fn poll(&mut self, own_ip: &str) {
+        let raw = multicast_service::get_peers().await;
+        let Some(heard) = parse_peers(&raw, &self.name, own_ip) else { return; };
+        let heard = match_servers(heard, &mut self.servers, Instant::now());
+        report_changes(&mut self.peers.lock().unwrap(), heard, &self.events);
+    }
+}
+
+// Record an announcement or withdrawal heard from `from`. False when the datagram isn't one of ours.
+fn apply_message(
+    servers: &mut HashMap<(String, IpAddr), (HostedServer, Instant)>,
+    datagram: &[u8],
+    from: IpAddr,
+    now: Instant,
+) -> bool {
+    let Ok(message) = serde_json::from_slice::<ServerMessage>(datagram) else { return false; };
+    match message {
+        ServerMessage::Announce { name, server } => {
+            servers.insert((name, from), (server, now));
+        }
+        ServerMessage::Withdraw { name } => {
+            servers.remove(&(name, from));
+        }
+    }
+    true
+}
+
+// The peers the multicast service lists, with the servers they announced recently; expired servers are forgotten.
+fn match_servers(
+    heard: Vec<(String, IpAddr)>,
+    servers: &mut HashMap<(String, IpAddr), (HostedServer, Instant)>,
+    now: Instant,
+) -> Vec<Peer> {
+    servers.retain(|_, (_, last_heard)| now.saturating_duration_since(*last_heard) <= SERVER_TIMEOUT);
+    heard.into_iter()
+        .map(|key| Peer {
+            hosting: servers.get(&key).map(|(server, _)| server.clone()),
+            name: key.0,
+            ip: key.1,
+            last_seen: now,
+        })
+        .collect()
+}
+
+// Everyone in a `get_peers()` result but ourselves. None when the result can't be read, so a garbled reply
+// doesn't report every peer lost.
+fn parse_peers(raw: &str, own_name: &str, own_ip: &str) -> Option<Vec<(String, IpAddr)>> {
+    let players: Vec<AnnouncedPlayer> = match serde_json::from_str(raw) {
+        Ok(players) => players,
+        Err(err) => {
+            debug!(%err, "unreadable peer list from the multicast service");
+            return None;
+        }
+    };
+    let peers = players.into_iter()
+        .filter(|player| !(player.name == own_name && player.ip == own_ip))
+        .filter_map(|player| Some((player.name, player.ip.parse().ok()?)))
+        .collect();
+    Some(peers)
+}
+
+fn report_changes(
+    known: &mut HashMap<(String, IpAddr), Peer>,
+    heard: Vec<Peer>,
+    events: &mpsc::UnboundedSender<DiscoveryEvent>,
+) {
+    let current: HashSet<(String, IpAddr)> = heard.iter().map(Peer::key).collect();
+    known.retain(|key, peer| {
+        let still_there = current.contains(key);
+        if !still_there {
+            info!(name = %peer.name, ip = %peer.ip, "peer lost");
+            let _ = events.send(DiscoveryEvent::PeerLost(peer.clone()));
+        }
+        still_there
+    });
+    for peer in heard {
+        match known.insert(peer.key(), peer.clone()) {
+            None => {
+                info!(name = %peer.name, ip = %peer.ip, hosting = peer.hosting.is_some(), "peer found");
+                let _ = events.send(DiscoveryEvent::PeerFound(peer));
+            }
+            Some(previous) if previous.hosting != peer.hosting => {
+                let _ = events.send(DiscoveryEvent::PeerUpdated(peer));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    fn room(room_id: &str, players: usize) -> AdvertisedRoom {
+        AdvertisedRoom { room_id: room_id.to_string(), players, capacity: 2 }
+    }
+
+    fn server(rooms: Vec<AdvertisedRoom>) -> HostedServer {
+        HostedServer { port: 3000, protocol_version: 4, rooms }
+    }
+
+    fn alice() -> Vec<(String, IpAddr)> {
+        vec![("alice".to_string(), ALICE_IP)]
+    }
+
+    fn announced_rooms(datagram: &[u8]) -> Vec<AdvertisedRoom> {
+        match serde_json::from_slice(datagram).unwrap() {
+            ServerMessage::Announce { server, .. } => server.rooms,
+            ServerMessage::Withdraw { .. } => panic!("expected an announcement"),
+        }
+    }
+
+    #[test]
+    fn announcement_keeps_every_room_when_it_fits() {
+        let rooms = vec![room("b", 2), room("a", 1)];
+        let datagram = encode_announcement("alice".to_string(), server(rooms));
+        // Open rooms first
+        assert_eq!(announced_rooms(&datagram), vec![room("a", 1), room("b", 2)]);
+    }
+
+    #[test]
+    fn announcement_drops_full_rooms_first_to_fit_one_datagram() {
+        let mut rooms: Vec<AdvertisedRoom> = (0..100).map(|i| room(&format!("full-room-{i:03}"), 2)).collect();
+        rooms.extend((0..10).map(|i| room(&format!("open-room-{i:03}"), 1)));
+        let datagram = encode_announcement("alice".to_string(), server(rooms));
+        assert!(datagram.len() <= MAX_DATAGRAM_SIZE);
+        let announced = announced_rooms(&datagram);
+        assert!(announced.len() < 110);
+        assert!(announced[..10].iter().all(AdvertisedRoom::is_open));
+        // One more room would not have fit
+        let mut one_more = serde_json::from_slice::<serde_json::Value>(&datagram).unwrap();
+        one_more["server"]["rooms"].as_array_mut().unwrap().push(serde_json::to_value(room("full-room-999", 2)).unwrap());
+        assert!(serde_json::to_vec(&one_more).unwrap().len() > MAX_DATAGRAM_SIZE);
+    }
+
+    #[test]
+    fn malformed_and_foreign_datagrams_are_ignored() {
+        let mut servers = HashMap::new();
+        let now = Instant::now();
+        for datagram in [
+            &b"\xff\x00 not json"[..],
+            br#"{"type":"announce","name":"alice"}"#,
+            br#"{"type":"hello","name":"alice"}"#,
+            br#"{"name":"alice","ip":"192.168.1.20"}"#,
+        ] {
+            assert!(!apply_message(&mut servers, datagram, ALICE_IP, now));
+        }
+        assert!(servers.is_empty());
+    }
+
+    #[test]
+    fn unreadable_peer_list_is_not_reported_as_everyone_lost() {
+        assert_eq!(parse_peers("garbage", "me", "192.168.1.10"), None);
+        let raw = r#"[{"name":"me","ip":"192.168.1.10"},{"name":"alice","ip":"192.168.1.20"},{"name":"bob","ip":"nowhere"}]"#;
+        assert_eq!(parse_peers(raw, "me", "192.168.1.10"), Some(alice()));
+    }
+
+    #[test]
+    fn announce_then_withdraw_updates_the_peer() {
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let mut servers = HashMap::new();
+        let mut known = HashMap::new();
+        let now = Instant::now();
+
+        let announce = encode_announcement("alice".to_string(), server(vec![room("fun", 1)]));
+        assert!(apply_message(&mut servers, &announce, ALICE_IP, now));
+        report_changes(&mut known, match_servers(alice(), &mut servers, now), &events_tx);
+        match events.try_recv() {
+            Ok(DiscoveryEvent::PeerFound(peer)) => assert_eq!(peer.hosting, Some(server(vec![room("fun", 1)]))),
+            _ => panic!("expected peer_found"),
+        }
+
+        // Same announcement again: nothing to report
+        report_changes(&mut known, match_servers(alice(), &mut servers, now), &events_tx);
+        assert!(events.try_recv().is_err());
+
+        let withdraw = serde_json::to_vec(&ServerMessage::Withdraw { name: "alice".to_string() }).unwrap();
+        assert!(apply_message(&mut servers, &withdraw, ALICE_IP, now));
+        report_changes(&mut known, match_servers(alice(), &mut servers, now), &events_tx);
+        match events.try_recv() {
+            Ok(DiscoveryEvent::PeerUpdated(peer)) => assert_eq!(peer.hosting, None),
+            _ => panic!("expected peer_updated"),
+        }
+
+        report_changes(&mut known, match_servers(Vec::new(), &mut servers, now), &events_tx);
+        assert!(matches!(events.try_recv(), Ok(DiscoveryEvent::PeerLost(_))));
+    }
+
+    #[test]
+    fn announcements_from_another_address_or_expired_ones_are_not_matched() {
+        let mut servers = HashMap::new();
+        let now = Instant::now();
+        let announce = encode_announcement("alice".to_string(), server(Vec::new()));
+        apply_message(&mut servers, &announce, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 99)), now);
+        assert_eq!(match_servers(alice(), &mut servers, now)[0].hosting, None);
+
+        apply_message(&mut servers, &announce, ALICE_IP, now);
+        let later = now + SERVER_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(match_servers(alice(), &mut servers, later)[0].hosting, None);
+        assert!(servers.is_empty());
+    }
+}
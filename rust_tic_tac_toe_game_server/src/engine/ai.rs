@@ -0,0 +1,95 @@
+use crate::engine::{Game, PlayerMark};
+
+// Score of `game` for `me`, searched to the end: wins count more the sooner they happen, losses the later.
+fn minimax(game: &Game, me: PlayerMark, mut alpha: i32, mut beta: i32) -> i32 {
+    if let Some(winner) = game.winner {
+        let score = 10 - game.moves_count as i32;
+        return if winner == me { score } else { -score };
+    }
+    if game.is_finished() {
+        return 0;
+    }
+    let maximizing = game.current_turn == me;
+    let mut best = if maximizing { i32::MIN } else { i32::MAX };
+    for (x, y) in game.empty_cells() {
+        let mut next = game.clone();
+        next.make_move(next.current_turn, x, y).expect("empty cell on an unfinished board");
+        let score = minimax(&next, me, alpha, beta);
+        if maximizing {
+            best = best.max(score);
+            alpha = alpha.max(score);
+        } else {
+            best = best.min(score);
+            beta = beta.min(score);
+        }
+        if beta <= alpha {
+            break;
+        }
+    }
+    best
+}
+
+/// The strongest move (x, y) for whoever's turn it is, None once the game is over. Perfect play: against it
+/// the best anyone can do is a draw. Ties go to the first cell row by row, so the answer is deterministic.
+pub fn best_move(game: &Game) -> Option<(u8, u8)> {
+    if game.is_finished() {
+        return None;
+    }
+    let me = game.current_turn;
+    let mut best: Option<((u8, u8), i32)> = None;
+    for (x, y) in game.empty_cells() {
+        let mut next = game.clone();
+        next.make_move(me, x, y).expect("empty cell on an unfinished board");
+        let score = minimax(&next, me, i32::MIN, i32::MAX);
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some(((x, y), score));
+        }
+    }
+    best.map(|(cell, _)| cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(moves: &[(u8, u8)]) -> Game {
+        let mut game = Game::new();
+        for &(x, y) in moves {
+            game.make_move(game.current_turn, x, y).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn takes_an_immediate_win() {
+        // x x .
+        // o o .
+        // . . .   X to move: (2, 0) wins, blocking (2, 1) doesn't
+        let game = play(&[(0, 0), (0, 1), (1, 0), (1, 1)]);
+        assert_eq!(best_move(&game), Some((2, 0)));
+    }
+
+    #[test]
+    fn blocks_an_immediate_loss() {
+        // x x .
+        // . o .
+        // . . .   O to move and must block at (2, 0)
+        let game = play(&[(0, 0), (1, 1), (1, 0)]);
+        assert_eq!(best_move(&game), Some((2, 0)));
+    }
+
+    #[test]
+    fn no_move_once_finished() {
+        let game = play(&[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)]);
+        assert_eq!(best_move(&game), None);
+    }
+
+    #[test]
+    fn perfect_play_against_itself_draws() {
+        let mut game = Game::new();
+        while let Some((x, y)) = best_move(&game) {
+            game.make_move(game.current_turn, x, y).unwrap();
+        }
+        assert!(game.is_draw());
+    }
+}
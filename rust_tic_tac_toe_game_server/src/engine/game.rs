@@ -0,0 +1,187 @@
+use std::fmt;
+use serde::Serialize;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerMark {
+    X,
+    O,
+}
+
+impl fmt::Display for PlayerMark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlayerMark::X => "x",
+            PlayerMark::O => "o",
+        })
+    }
+}
+
+impl PlayerMark {
+    pub fn opponent(self) -> PlayerMark {
+        match self {
+            PlayerMark::X => PlayerMark::O,
+            PlayerMark::O => PlayerMark::X,
+        }
+    }
+}
+
+// Indexed board[y][x], like on the wire
+pub type Board = [[Option<PlayerMark>; 3]; 3];
+
+/// The rules of one game of tic tac toe, without players, rooms or networking. X always moves first.
+#[derive(Clone, Debug)]
+pub struct Game {
+    pub board: Board,
+    pub current_turn: PlayerMark,
+    pub winner: Option<PlayerMark>,
+    pub moves_count: u8,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self { board: [[None; 3]; 3], current_turn: PlayerMark::X, winner: None, moves_count: 0 }
+    }
+
+    // A game is over once someone has won or the board is full
+    pub fn is_finished(&self) -> bool {
+        self.winner.is_some() || self.moves_count >= 9
+    }
+
+    pub fn is_draw(&self) -> bool {
+        self.winner.is_none() && self.moves_count >= 9
+    }
+
+    // Free cells as (x, y), row by row.
+    pub fn empty_cells(&self) -> Vec<(u8, u8)> {
+        (0..3u8)
+            .flat_map(|y| (0..3u8).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.board[y as usize][x as usize].is_none())
+            .collect()
+    }
+
+    pub fn make_move(&mut self, player: PlayerMark, x: u8, y: u8) -> Result<(), &'static str> {
+        if self.is_finished() {
+            return Err("game_already_finished");
+        }
+        if player != self.current_turn {
+            return Err("not_your_turn");
+        }
+        if x > 2 || y > 2 {
+            return Err("out_of_bounds");
+        }
+        let xi = x as usize;
+        let yi = y as usize;
+        if self.board[yi][xi].is_some() {
+            return Err("cell_occupied");
+        }
+        self.board[yi][xi] = Some(player);
+        self.moves_count += 1;
+        // Check winner or draw
+        if let Some(winner) = check_winner(&self.board) {
+            self.winner = Some(winner);
+        } else if self.moves_count >= 9 {
+            // draw -> winner stays None but game considered finished
+        } else {
+            self.current_turn = self.current_turn.opponent();
+        }
+        Ok(())
+    }
+}
+
+pub fn check_winner(board: &Board) -> Option<PlayerMark> {
+    let lines = [
+        // Rows
+        [(0,0),(1,0),(2,0)],
+        [(0,1),(1,1),(2,1)],
+        [(0,2),(1,2),(2,2)],
+        // Cols
+        [(0,0),(0,1),(0,2)],
+        [(1,0),(1,1),(1,2)],
+        [(2,0),(2,1),(2,2)],
+        // Diagonals
+        [(0,0),(1,1),(2,2)],
+        [(2,0),(1,1),(0,2)],
+    ];
+    for line in lines.iter() {
+        let [a,b,c] = line;
+        if let (Some(m1), Some(m2), Some(m3)) = (board[a.1][a.0], board[b.1][b.0], board[c.1][c.0]) {
+            if m1 == m2 && m2 == m3 { return Some(m1); }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Play (x, y) moves in turn, starting with X
+    fn play(moves: &[(u8, u8)]) -> Game {
+        let mut game = Game::new();
+        for &(x, y) in moves {
+            game.make_move(game.current_turn, x, y).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn three_in_a_row_wins() {
+        // X takes the top row while O plays the middle one
+        let game = play(&[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)]);
+        assert_eq!(game.winner, Some(PlayerMark::X));
+        assert!(game.is_finished());
+        assert!(!game.is_draw());
+        // The winner keeps the turn
+        assert_eq!(game.current_turn, PlayerMark::X);
+    }
+
+    #[test]
+    fn full_board_without_a_line_is_a_draw() {
+        // x o x
+        // x o o
+        // o x x
+        let game = play(&[(0, 0), (1, 0), (2, 0), (1, 1), (0, 1), (2, 1), (1, 2), (0, 2), (2, 2)]);
+        assert_eq!(game.winner, None);
+        assert!(game.is_finished());
+        assert!(game.is_draw());
+        assert!(game.empty_cells().is_empty());
+    }
+
+    #[test]
+    fn turns_alternate_from_x() {
+        let mut game = Game::new();
+        assert_eq!(game.current_turn, PlayerMark::X);
+        assert_eq!(game.make_move(PlayerMark::O, 0, 0), Err("not_your_turn"));
+        game.make_move(PlayerMark::X, 0, 0).unwrap();
+        assert_eq!(game.current_turn, PlayerMark::O);
+        assert_eq!(game.make_move(PlayerMark::X, 1, 1), Err("not_your_turn"));
+    }
+
+    #[test]
+    fn rejects_moves_off_the_board_or_on_taken_cells() {
+        let mut game = play(&[(1, 1)]);
+        assert_eq!(game.make_move(PlayerMark::O, 3, 0), Err("out_of_bounds"));
+        assert_eq!(game.make_move(PlayerMark::O, 0, 3), Err("out_of_bounds"));
+        assert_eq!(game.make_move(PlayerMark::O, 1, 1), Err("cell_occupied"));
+        // Rejected moves change nothing
+        assert_eq!(game.moves_count, 1);
+        assert_eq!(game.current_turn, PlayerMark::O);
+    }
+
+    #[test]
+    fn finished_games_reject_every_move() {
+        let mut won = play(&[(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)]);
+        assert_eq!(won.make_move(PlayerMark::O, 2, 2), Err("game_already_finished"));
+        assert_eq!(won.make_move(PlayerMark::X, 2, 2), Err("game_already_finished"));
+        // On a draw the finished check comes before the turn and cell checks
+        let mut drawn = play(&[(0, 0), (1, 0), (2, 0), (1, 1), (0, 1), (2, 1), (1, 2), (0, 2), (2, 2)]);
+        assert_eq!(drawn.make_move(PlayerMark::O, 0, 0), Err("game_already_finished"));
+        assert_eq!(drawn.make_move(PlayerMark::X, 0, 0), Err("game_already_finished"));
+    }
+}
@@ -0,0 +1,10 @@
+mod game;
+// The AI only plays in `LocalGame`
+#[cfg(feature = "godot")]
+mod ai;
+#[cfg(feature = "godot")]
+mod node;
+
+pub use game::{Board, Game, PlayerMark};
+#[cfg(feature = "godot")]
+pub use ai::best_move;
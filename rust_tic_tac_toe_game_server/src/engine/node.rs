@@ -0,0 +1,149 @@
+use godot::classes::Node;
+use godot::prelude::*;
+use crate::engine::{best_move, Game, PlayerMark};
+
+/// One game on this device, hot-seat or against the built-in AI, without a server. Reports the game through
+/// the same `game_state_changed` / `move_made` signals and state keys as `TicTacToeClient`.
+#[derive(GodotClass)]
+#[class(init, base=Node)]
+struct LocalGame {
+    // Chance (0 to 1) that `ai_move` plays a random free cell instead of the best one; at 0 it never loses
+    #[var]
+    ai_mistake_chance: f64,
+    game: Game,
+    base: Base<Node>,
+}
+
+// Missing values (no winner, no current turn, empty cells) are empty strings, which GDScript tests as falsy.
+fn mark_string(mark: Option<PlayerMark>) -> GString {
+    mark.map(|m| GString::from(m.to_string().as_str())).unwrap_or_default()
+}
+
+fn game_state_dict(game: &Game) -> Dictionary {
+    // board[y][x], like on the wire
+    let board: VariantArray = game.board.iter()
+        .map(|row| row.iter().map(|cell| mark_string(*cell)).collect::<Array<GString>>().to_variant())
+        .collect();
+    let mut dict = Dictionary::new();
+    dict.set("board", board);
+    dict.set("current_turn", mark_string(Some(game.current_turn).filter(|_| !game.is_finished())));
+    dict.set("winner", mark_string(game.winner));
+    dict.set("started", true);
+    dict.set("moves_count", game.moves_count as i64);
+    dict
+}
+
+// Uniform in [0, 1).
+fn random_unit() -> f64 {
+    getrandom::u32().map_or(0.0, |n| n as f64 / (u32::MAX as f64 + 1.0))
+}
+
+#[godot_api]
+impl LocalGame {
+    #[signal]
+    fn game_state_changed(state: Dictionary);
+    #[signal]
+    fn move_made(x: i64, y: i64, mark: GString);
+    // Winner's mark, empty for a draw
+    #[signal]
+    fn game_finished(winner: GString);
+    // Same codes the server uses: `not_your_turn` never happens here, the mover is always whoever's turn it is
+    #[signal]
+    fn move_rejected(code: GString);
+
+    // Clear the board; X moves first.
+    #[func]
+    fn new_game(&mut self) {
+        self.game = Game::new();
+        let state = game_state_dict(&self.game);
+        self.signals().game_state_changed().emit(&state);
+    }
+
+    // Place the mark of whoever's turn it is. False (after `move_rejected`) if the move isn't allowed.
+    #[func]
+    fn make_move(&mut self, x: i64, y: i64) -> bool {
+        let (Ok(x), Ok(y)) = (u8::try_from(x), u8::try_from(y)) else {
+            self.signals().move_rejected().emit(&GString::from("out_of_bounds"));
+            return false;
+        };
+        self.play(x, y)
+    }
+
+    // Let the AI take the current turn, e.g. right after the human's `make_move` in a vs-CPU game.
+    #[func]
+    fn ai_move(&mut self) -> bool {
+        let free = self.game.empty_cells();
+        let cell = if !free.is_empty() && random_unit() < self.ai_mistake_chance {
+            Some(free[getrandom::u32().unwrap_or(0) as usize % free.len()])
+        } else {
+            best_move(&self.game)
+        };
+        match cell {
+            Some((x, y)) => self.play(x, y),
+            None => {
+                self.signals().move_rejected().emit(&GString::from("game_already_finished"));
+                false
+            }
+        }
+    }
+
+    // The AI's best move for the current turn as (x, y), (-1, -1) once the game is over. Doesn't play it.
+    #[func]
+    fn suggest_move(&self) -> Vector2i {
+        best_move(&self.game).map_or(Vector2i::new(-1, -1), |(x, y)| Vector2i::new(x as i32, y as i32))
+    }
+
+    // Mark on the cell, empty if free or out of bounds.
+    #[func]
+    fn get_cell(&self, x: i64, y: i64) -> GString {
+        let cell = usize::try_from(y).ok().and_then(|y| self.game.board.get(y))
+            .and_then(|row| usize::try_from(x).ok().and_then(|x| row.get(x)))
+            .copied()
+            .flatten();
+        mark_string(cell)
+    }
+
+    // Mark to move next, empty once the game is over.
+    #[func]
+    fn get_current_turn(&self) -> GString {
+        mark_string(Some(self.game.current_turn).filter(|_| !self.game.is_finished()))
+    }
+
+    #[func]
+    fn get_winner(&self) -> GString {
+        mark_string(self.game.winner)
+    }
+
+    #[func]
+    fn is_finished(&self) -> bool {
+        self.game.is_finished()
+    }
+
+    #[func]
+    fn is_draw(&self) -> bool {
+        self.game.is_draw()
+    }
+
+    #[func]
+    fn get_game_state(&self) -> Dictionary {
+        game_state_dict(&self.game)
+    }
+}
+
+impl LocalGame {
+    fn play(&mut self, x: u8, y: u8) -> bool {
+        let mark = self.game.current_turn;
+        if let Err(code) = self.game.make_move(mark, x, y) {
+            self.signals().move_rejected().emit(&GString::from(code));
+            return false;
+        }
+        self.signals().move_made().emit(x as i64, y as i64, &mark_string(Some(mark)));
+        let state = game_state_dict(&self.game);
+        self.signals().game_state_changed().emit(&state);
+        if self.game.is_finished() {
+            let winner = mark_string(self.game.winner);
+            self.signals().game_finished().emit(&winner);
+        }
+        true
+    }
+}
@@ -0,0 +1,436 @@
+use godot::prelude::*;
+#[cfg(feature = "discovery")]
+use rust_udp_multicast_test::multicast_service;
+
+use std::io::Write;
+#[cfg(feature = "server")]
+use std::sync::Arc;
+#[cfg(feature = "discovery")]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(any(feature = "server", feature = "discovery"))]
+use godot::init::InitLevel;
+#[cfg(any(feature = "server", feature = "discovery"))]
+use tokio::sync::mpsc;
+#[cfg(feature = "server")]
+use tokio::sync::oneshot;
+use crate::logging;
+#[cfg(any(feature = "server", feature = "discovery"))]
+use crate::runtime;
+#[cfg(all(feature = "server", feature = "client"))]
+use crate::client::{LocalEvent, LocalLink, TicTacToeClient};
+#[cfg(feature = "discovery")]
+use crate::discovery::{self, AdvertisedRoom, DiscoveryEvent, DiscoveryHandle, Peer};
+#[cfg(all(feature = "server", feature = "discovery"))]
+use crate::discovery::{HostedServer, HostingSource};
+#[cfg(all(feature = "server", feature = "client"))]
+use crate::room::{join_local, Payload};
+#[cfg(all(feature = "server", feature = "discovery"))]
+use crate::room::PROTOCOL_VERSION;
+#[cfg(feature = "server")]
+use crate::server::{self, AppState, SharedState};
+#[cfg(all(feature = "server", feature = "discovery"))]
+use crate::server::ROOM_CAPACITY;
+
+// Buffers one formatted tracing event and prints it to Godot's output panel when the subscriber drops it.
+#[derive(Default)]
+struct GodotWriter {
+    buf: Vec<u8>,
+}
+
+impl Write for GodotWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for GodotWriter {
+    fn drop(&mut self) {
+        let line = String::from_utf8_lossy(&self.buf);
+        godot_print!("{}", line.trim_end());
+    }
+}
+
+struct MyExtension;
+
+// The extension is `reloadable`: everything running on the Tokio runtime has to go when the library unloads,
+// or a hot reload in the editor leaks its threads and keeps the server port and discovery socket bound.
+#[gdextension]
+unsafe impl ExtensionLibrary for MyExtension {
+    #[cfg(any(feature = "server", feature = "discovery"))]
+    fn on_level_init(level: InitLevel) {
+        if level == InitLevel::Scene {
+            runtime::start();
+        }
+    }
+
+    #[cfg(any(feature = "server", feature = "discovery"))]
+    fn on_level_deinit(level: InitLevel) {
+        if level == InitLevel::Scene {
+            // Servers close their rooms and free the port, discovery says goodbye, then the threads are joined
+            runtime::shutdown();
+        }
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base=Node)]
+struct RustNode {
+    #[cfg(feature = "discovery")]
+    discovery: Option<DiscoveryHandle>,
+    // Filled by the discovery task, drained on the main thread in `process`
+    #[cfg(feature = "discovery")]
+    discovery_events: Option<mpsc::UnboundedReceiver<DiscoveryEvent>>,
+    // Game server started from this node, advertised over discovery while it's listening
+    #[cfg(feature = "server")]
+    server: Option<LocalServer>,
+    base: Base<Node>,
+}
+
+// Reported by the server task, drained on the main thread in `process`
+#[cfg(feature = "server")]
+enum ServerEvent {
+    Started(u16),
+    // Bind or serve error; the server is gone
+    Failed(String),
+    Stopped,
+}
+
+#[cfg(feature = "server")]
+struct LocalServer {
+    state: SharedState,
+    // Sending, or dropping it with the node, stops the server; None once a stop was requested
+    shutdown: Option<oneshot::Sender<()>>,
+    events: mpsc::UnboundedReceiver<ServerEvent>,
+    // Set once the server is listening
+    port: Option<u16>,
+}
+
+// Advertise the server's port, protocol version and live room counts.
+#[cfg(all(feature = "server", feature = "discovery"))]
+fn hosting_source(state: SharedState) -> HostingSource {
+    Arc::new(move || HostedServer {
+        port: state.config.port,
+        protocol_version: PROTOCOL_VERSION,
+        rooms: state.room_summaries().into_iter()
+            .map(|r| AdvertisedRoom { room_id: r.room_id, players: r.players, capacity: ROOM_CAPACITY })
+            .collect(),
+    })
+}
+
+// Relay between a `TicTacToeClient`'s local link and its seat in `room_id`, until either side goes away.
+#[cfg(all(feature = "server", feature = "client"))]
+async fn run_local_seat(
+    state: SharedState,
+    room_id: String,
+    token: Option<String>,
+    mut commands: mpsc::UnboundedReceiver<Payload>,
+    events: mpsc::UnboundedSender<LocalEvent>,
+) {
+    let mut seat = match join_local(&state, &room_id, token.as_deref()).await {
+        Ok(seat) => seat,
+        // Like a socket the server rejects: the client never sees `connected`, only the reason and the close
+        Err(message) => {
+            let _ = events.send(LocalEvent::Message(message));
+            let _ = events.send(LocalEvent::Closed);
+            return;
+        }
+    };
+    let _ = events.send(LocalEvent::Opened);
+    loop {
+        tokio::select! {
+            message = seat.recv() => match message {
+                Some(message) => { let _ = events.send(LocalEvent::Message(message)); }
+                None => {
+                    let _ = events.send(LocalEvent::Closed);
+                    break;
+                }
+            },
+            command = commands.recv() => match command {
+                Some(payload) => { seat.send(payload).await; }
+                // The client left the room or was freed
+                None => break,
+            },
+        }
+    }
+    seat.leave().await;
+}
+
+#[cfg(feature = "discovery")]
+fn room_dict(room: &AdvertisedRoom) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("room_id", GString::from(room.room_id.as_str()));
+    dict.set("players", room.players as i64);
+    dict.set("capacity", room.capacity as i64);
+    dict.set("open", room.is_open());
+    dict
+}
+
+#[cfg(feature = "discovery")]
+fn peer_dict(peer: &Peer) -> Dictionary {
+    // Unix time in seconds, comparable with `Time.get_unix_time_from_system()`
+    let last_seen = (SystemTime::now() - peer.last_seen.elapsed())
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64());
+    let hosting = peer.hosting.as_ref();
+    // Ready for `TicTacToeClient.join_room`, empty when the peer isn't hosting
+    let url = hosting.map(|h| format!("ws://{}:{}", peer.ip, h.port)).unwrap_or_default();
+    let rooms: Array<Dictionary> = hosting.map(|h| h.rooms.iter().map(room_dict).collect()).unwrap_or_default();
+    let mut dict = Dictionary::new();
+    dict.set("name", GString::from(peer.name.as_str()));
+    dict.set("ip", GString::from(peer.ip.to_string().as_str()));
+    dict.set("hosting", hosting.is_some());
+    // 0 when the peer isn't hosting a game server
+    dict.set("port", hosting.map_or(0, |h| h.port as i64));
+    dict.set("protocol_version", hosting.map_or(0, |h| h.protocol_version as i64));
+    dict.set("url", GString::from(url.as_str()));
+    dict.set("rooms", rooms);
+    dict.set("last_seen", last_seen);
+    dict
+}
+
+#[godot_api]
+impl INode for RustNode {
+    fn init(base: Base<Node>) -> Self {
+        // Running embedded: send server logs to the Godot console instead of a (usually invisible) stdout
+        logging::init_with_writer(logging::LogFormat::Plain, GodotWriter::default);
+        RustNode {
+            #[cfg(feature = "discovery")]
+            discovery: None,
+            #[cfg(feature = "discovery")]
+            discovery_events: None,
+            #[cfg(feature = "server")]
+            server: None,
+            base,
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        #[cfg(feature = "server")]
+        self.process_server_events();
+        #[cfg(feature = "discovery")]
+        self.process_discovery_events();
+    }
+}
+
+#[godot_api]
+impl RustNode {
+    // Peers are `{name, ip, hosting, port, protocol_version, url, rooms, last_seen}`, see `peer_dict`
+    #[cfg(feature = "discovery")]
+    #[signal]
+    fn peer_found(peer: Dictionary);
+    // The peer started or stopped hosting a game server, or a player joined or left one of its rooms
+    #[cfg(feature = "discovery")]
+    #[signal]
+    fn peer_updated(peer: Dictionary);
+    // Emitted when the peer stops its discovery service or drops off the LAN
+    #[cfg(feature = "discovery")]
+    #[signal]
+    fn peer_lost(peer: Dictionary);
+
+    // The server is listening on `port`
+    #[cfg(feature = "server")]
+    #[signal]
+    fn server_started(port: i64);
+    // Binding the port (or serving) failed; the server is gone and may be started again
+    #[cfg(feature = "server")]
+    #[signal]
+    fn server_failed(reason: GString);
+    // After `stop_tic_tac_toe_server`, once every room was closed
+    #[cfg(feature = "server")]
+    #[signal]
+    fn server_stopped();
+
+    // This function will be callable from Godot
+    #[func]
+    fn hello_world(&self) {
+        godot_print!("Hello from Rust!");
+    }
+
+    // Announce `player_name` on the LAN and report other instances through the `peer_*` signals.
+    #[cfg(feature = "discovery")]
+    #[func]
+    fn start_discovery_service(&mut self, player_name: GString) {
+        self.stop_discovery_service();
+        let (tx, rx) = mpsc::unbounded_channel();
+        match discovery::start(player_name.to_string(), tx, runtime::stopping().wait()) {
+            Ok((handle, service)) => {
+                runtime::spawn_service(service);
+                #[cfg(feature = "server")]
+                handle.set_hosting(self.hosting());
+                self.discovery = Some(handle);
+                self.discovery_events = Some(rx);
+            }
+            Err(err) => godot_error!("Failed to start discovery service: {}", err),
+        }
+    }
+
+    // Peers currently known, sorted by name, in the same shape as the `peer_*` signals. Never blocks.
+    #[cfg(feature = "discovery")]
+    #[func]
+    fn discover_peers(&self) -> Array<Dictionary> {
+        let peers = self.discovery.as_ref().map(|d| d.peers()).unwrap_or_default();
+        peers.iter().map(peer_dict).collect()
+    }
+
+    #[cfg(feature = "discovery")]
+    #[func]
+    fn stop_discovery_service(&mut self) {
+        self.discovery = None;
+        self.discovery_events = None;
+    }
+
+    #[cfg(feature = "discovery")]
+    #[func]
+    fn get_local_ip(&self) -> GString {
+        let local_ip = multicast_service::get_local_ipv4_in_string();
+        local_ip.to_godot()
+    }
+
+    // Start the game server on the default port. Returns right away: the outcome arrives as `server_started`
+    // or `server_failed`. False if a server is already starting or running.
+    #[cfg(feature = "server")]
+    #[func]
+    fn start_tic_tac_toe_server(&mut self) -> bool {
+        if self.server.is_some() {
+            return false;
+        }
+        let state: SharedState = Arc::new(AppState::new(server::ServerConfig::default()));
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        self.server = Some(LocalServer { state: state.clone(), shutdown: Some(shutdown), events, port: None });
+        let stopping = runtime::stopping();
+        runtime::spawn_service(async move {
+            let port = state.config.port;
+            let event = match server::bind(port).await {
+                Err(err) => ServerEvent::Failed(format!("cannot listen on port {port}: {err}")),
+                Ok(listener) => {
+                    let port = listener.local_addr().map_or(port, |addr| addr.port());
+                    let _ = events_tx.send(ServerEvent::Started(port));
+                    // Stopped from Godot, by freeing the node, or by the extension unloading
+                    let shutdown = async {
+                        tokio::select! {
+                            _ = shutdown_rx => {}
+                            _ = stopping.wait() => {}
+                        }
+                    };
+                    match server::serve_state_until(listener, state, shutdown).await {
+                        Ok(()) => ServerEvent::Stopped,
+                        Err(err) => ServerEvent::Failed(err.to_string()),
+                    }
+                }
+            };
+            let _ = events_tx.send(event);
+        });
+        true
+    }
+
+    // Seat `client` in `room_id` of the server this node runs, without a socket: the host plays in its own
+    // server through the usual `TicTacToeClient` signals while remote players connect over WebSockets. False
+    // unless the server is running.
+    #[cfg(all(feature = "server", feature = "client"))]
+    #[func]
+    fn join_hosted_room(&mut self, mut client: Gd<TicTacToeClient>, room_id: GString) -> bool {
+        let Some(state) = self.server.as_ref().filter(|s| s.port.is_some()).map(|s| s.state.clone()) else {
+            return false;
+        };
+        let (commands_tx, commands) = mpsc::unbounded_channel();
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let token = client.bind().token();
+        runtime::spawn(run_local_seat(state, room_id.to_string(), token, commands, events));
+        client.bind_mut().join_local(LocalLink { commands: commands_tx, events: events_rx });
+        true
+    }
+
+    // Close every room (players get a notice) and stop listening; `server_stopped` follows.
+    #[cfg(feature = "server")]
+    #[func]
+    fn stop_tic_tac_toe_server(&mut self) {
+        if let Some(shutdown) = self.server.as_mut().and_then(|s| s.shutdown.take()) {
+            let _ = shutdown.send(());
+        }
+    }
+
+    // `stopped`, `starting`, `running` or `stopping`.
+    #[cfg(feature = "server")]
+    #[func]
+    fn get_server_status(&self) -> GString {
+        let status = match &self.server {
+            None => "stopped",
+            Some(server) if server.shutdown.is_none() => "stopping",
+            Some(LocalServer { port: None, .. }) => "starting",
+            Some(_) => "running",
+        };
+        GString::from(status)
+    }
+
+    // Port the server listens on, 0 unless it's running.
+    #[cfg(feature = "server")]
+    #[func]
+    fn get_server_port(&self) -> i64 {
+        self.server.as_ref().and_then(|s| s.port).map_or(0, i64::from)
+    }
+}
+
+impl RustNode {
+    // What discovery should advertise: the server once it's listening.
+    #[cfg(all(feature = "server", feature = "discovery"))]
+    fn hosting(&self) -> Option<HostingSource> {
+        self.server.as_ref().filter(|s| s.port.is_some()).map(|s| hosting_source(s.state.clone()))
+    }
+
+    #[cfg(feature = "discovery")]
+    fn process_discovery_events(&mut self) {
+        let Some(events) = self.discovery_events.as_mut() else { return; };
+        let mut pending = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            pending.push(event);
+        }
+        for event in pending {
+            match event {
+                DiscoveryEvent::PeerFound(peer) => self.signals().peer_found().emit(&peer_dict(&peer)),
+                DiscoveryEvent::PeerUpdated(peer) => self.signals().peer_updated().emit(&peer_dict(&peer)),
+                DiscoveryEvent::PeerLost(peer) => self.signals().peer_lost().emit(&peer_dict(&peer)),
+            }
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn process_server_events(&mut self) {
+        let Some(server) = self.server.as_mut() else { return; };
+        let mut pending = Vec::new();
+        while let Ok(event) = server.events.try_recv() {
+            pending.push(event);
+        }
+        for event in pending {
+            match event {
+                ServerEvent::Started(port) => {
+                    if let Some(server) = self.server.as_mut() { server.port = Some(port); }
+                    #[cfg(feature = "discovery")]
+                    if let Some(discovery) = &self.discovery { discovery.set_hosting(self.hosting()); }
+                    self.signals().server_started().emit(port as i64);
+                }
+                ServerEvent::Failed(reason) => {
+                    godot_error!("Tic tac toe server failed: {}", reason);
+                    self.server_gone();
+                    self.signals().server_failed().emit(&GString::from(reason.as_str()));
+                }
+                ServerEvent::Stopped => {
+                    self.server_gone();
+                    self.signals().server_stopped().emit();
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "server")]
+    fn server_gone(&mut self) {
+        self.server = None;
+        #[cfg(feature = "discovery")]
+        if let Some(discovery) = &self.discovery { discovery.set_hosting(None); }
+    }
+}
@@ -1,84 +1,30 @@
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "server")]
+mod auth;
+#[cfg(feature = "server")]
+mod cors;
+pub mod logging;
+#[cfg(feature = "server")]
+mod metrics;
+#[cfg(feature = "server")]
+mod rate_limit;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(any(feature = "server", feature = "client"))]
 mod room;
-
-use godot::prelude::*;
-use rust_udp_multicast_test::multicast_service;
-
-use std::sync::{mpsc, OnceLock};
-use tokio::runtime::Runtime;
-
-static TOKIO_RUNTIME: OnceLock<Runtime> = OnceLock::new();
-
-fn get_runtime() -> &'static Runtime {
-    TOKIO_RUNTIME.get_or_init(|| Runtime::new().expect("Failed to create Tokio runtime"))
-}
-
-pub fn spawn<F>(future: F)
-where
-    F: std::future::Future<Output = ()> + Send + 'static,
-{
-    get_runtime().spawn(future);
-}
-
-struct MyExtension;
-
-#[gdextension]
-unsafe impl ExtensionLibrary for MyExtension {}
-
-#[derive(GodotClass)]
-#[class(base=Node)]
-struct RustNode {
-    base: Base<Node>,
-}
-
-#[godot_api]
-impl INode for RustNode {
-    fn init(base: Base<Node>) -> Self {
-        RustNode { base }
-    }
-}
-
-#[godot_api]
-impl RustNode {
-    // This function will be callable from Godot
-    #[func]
-    fn hello_world(&self) {
-        godot_print!("Hello from Rust!");
-    }
-
-    #[func]
-    fn start_discovery_service(&self, player_name: GString) {
-        spawn(async move {
-            multicast_service::start_service(player_name.to_string()).await;
-        })
-    }
-
-    #[func]
-    fn discover_peers(&self) -> GString {
-        let (tx, rx) = mpsc::channel();
-        spawn(async move {
-            let peers = multicast_service::get_peers().await;
-            let _ = tx.send(peers);
-        });
-        let peers = rx.recv().unwrap_or_default();
-        peers.to_godot()
-    }
-
-    #[func]
-    fn stop_discovery_service(&self) {
-        spawn(async move { multicast_service::stop_service().await })
-    }
-
-    #[func]
-    fn get_local_ip(&self) -> GString {
-        let local_ip = multicast_service::get_local_ipv4_in_string();
-        local_ip.to_godot()
-    }
-
-    #[func]
-    fn start_tic_tac_toe_server(&self) {
-        spawn(async move {
-            server::start_server().await;
-        })
-    }
-}
+#[cfg(feature = "client")]
+mod client;
+// The game rules on their own, for other front ends; the server and `LocalGame` build on them
+#[cfg(feature = "engine")]
+pub mod engine;
+#[cfg(feature = "discovery")]
+mod discovery;
+#[cfg(all(feature = "godot", any(feature = "server", feature = "discovery")))]
+mod runtime;
+// `RustNode` and the extension entry point; without it the library is only what the server binary needs
+#[cfg(feature = "godot")]
+mod extension;
+
+#[cfg(all(feature = "godot", any(feature = "server", feature = "discovery")))]
+pub use runtime::spawn;
@@ -0,0 +1,47 @@
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+/// Output format of the tracing subscriber installed by `init`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    // Multi-line, human readable
+    Pretty,
+    // One JSON object per event, for log shippers
+    Json,
+    // Single line without colors, used when forwarding to Godot's output panel
+    Plain,
+}
+
+impl LogFormat {
+    // Read from `TICTACTOE_LOG_FORMAT` (`pretty`, `json` or `plain`), defaulting to pretty
+    pub fn from_env() -> Self {
+        match std::env::var("TICTACTOE_LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            Ok("plain") => LogFormat::Plain,
+            _ => LogFormat::Pretty,
+        }
+    }
+}
+
+// Verbosity comes from `RUST_LOG` (e.g. `RUST_LOG=rust_tic_tac_toe_game_server=debug`), `info` otherwise.
+pub fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+pub fn init(format: LogFormat) {
+    init_with_writer(format, std::io::stdout);
+}
+
+// Install the global subscriber writing to `make_writer`. A no-op if one is already installed, e.g. after
+// the Godot extension was hot-reloaded.
+pub fn init_with_writer<W>(format: LogFormat, make_writer: W)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt().with_env_filter(env_filter()).with_writer(make_writer);
+    let _ = match format {
+        LogFormat::Pretty => builder.pretty().try_init(),
+        LogFormat::Json => builder.json().try_init(),
+        LogFormat::Plain => builder.compact().with_ansi(false).try_init(),
+    };
+}
@@ -1,4 +1,35 @@
+use rust_tic_tac_toe_game_server::logging::{self, LogFormat};
+use rust_tic_tac_toe_game_server::server::{self, ServerConfig};
+
+// `TICTACTOE_TLS_CERT` + `TICTACTOE_TLS_KEY` enable wss://; `TICTACTOE_TLS_SELF_SIGNED=host1,10.0.0.5` generates
+// a self-signed pair at those paths on first start.
+#[cfg(feature = "tls")]
+fn tls_from_env() -> Option<server::TlsConfig> {
+    let cert_path = std::env::var("TICTACTOE_TLS_CERT").ok()?;
+    let key_path = std::env::var("TICTACTOE_TLS_KEY").ok()?;
+    let self_signed_for = std::env::var("TICTACTOE_TLS_SELF_SIGNED")
+        .ok()
+        .map(|names| names.split(',').map(|n| n.trim().to_string()).filter(|n| !n.is_empty()).collect());
+    Some(server::TlsConfig { cert_path: cert_path.into(), key_path: key_path.into(), self_signed_for })
+}
+
 #[tokio::main]
 async fn main() {
-    rust_tic_tac_toe_game_server::server::start_server().await;
-}
\ No newline at end of file
+    logging::init(LogFormat::from_env());
+    let config = ServerConfig {
+        admin_token: std::env::var("TICTACTOE_ADMIN_TOKEN").ok().filter(|t| !t.is_empty()),
+        auth_secret: std::env::var("TICTACTOE_AUTH_SECRET").ok().filter(|s| !s.is_empty()),
+        require_auth: std::env::var("TICTACTOE_REQUIRE_AUTH").is_ok_and(|v| v == "1" || v == "true"),
+        // Comma-separated, e.g. `https://game.example.com,http://localhost:8060`
+        allowed_origins: std::env::var("TICTACTOE_ALLOWED_ORIGINS")
+            .ok()
+            .map(|origins| origins.split(',').map(|o| o.trim().to_string()).filter(|o| !o.is_empty()).collect()),
+        #[cfg(feature = "tls")]
+        tls: tls_from_env(),
+        ..ServerConfig::default()
+    };
+    if let Err(err) = server::start_server_with_config(config).await {
+        tracing::error!(%err, "server failed");
+        std::process::exit(1);
+    }
+}
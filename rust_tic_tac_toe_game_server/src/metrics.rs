@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use prometheus::{Encoder, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use crate::room::QueueMetrics;
+use crate::server::SharedState;
+
+/// Prometheus instruments for the whole server, rendered by the `/metrics` endpoint.
+pub struct Metrics {
+    registry: Registry,
+    pub active_rooms: IntGauge,
+    pub connected_players: IntGauge,
+    // Rate this to get moves per second, e.g. `rate(tictactoe_moves_total[1m])`
+    pub moves: IntCounter,
+    // Labelled by outcome: x_won, o_won, draw
+    pub games_finished: IntCounterVec,
+    // Labelled by the error code sent to the client
+    pub errors: IntCounterVec,
+    pub ws_connects: IntCounter,
+    pub ws_disconnects: IntCounter,
+    pub queue: Arc<QueueMetrics>,
+}
+
+fn counter(registry: &Registry, name: &str, help: &str) -> IntCounter {
+    let counter = IntCounter::new(name, help).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+fn counter_vec(registry: &Registry, name: &str, help: &str, label: &str) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), &[label]).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+fn gauge(registry: &Registry, name: &str, help: &str) -> IntGauge {
+    let gauge = IntGauge::new(name, help).unwrap();
+    registry.register(Box::new(gauge.clone())).unwrap();
+    gauge
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        // A registry per server (rather than the global default) keeps several in-process servers apart
+        let registry = Registry::new();
+        let queue = Arc::new(QueueMetrics {
+            dropped: counter(&registry, "tictactoe_outbound_dropped_total", "Messages dropped from full outbound queues"),
+            coalesced: counter(&registry, "tictactoe_outbound_coalesced_total", "Queued game updates replaced by newer ones"),
+            disconnected: counter(&registry, "tictactoe_outbound_overflow_disconnects_total", "Connections closed because their outbound queue overflowed"),
+        });
+        Self {
+            active_rooms: gauge(&registry, "tictactoe_active_rooms", "Rooms with at least one player"),
+            connected_players: gauge(&registry, "tictactoe_connected_players", "Players seated in a room"),
+            moves: counter(&registry, "tictactoe_moves_total", "Moves applied"),
+            games_finished: counter_vec(&registry, "tictactoe_games_finished_total", "Games finished, by outcome", "outcome"),
+            errors: counter_vec(&registry, "tictactoe_errors_total", "Error responses sent to clients, by code", "code"),
+            ws_connects: counter(&registry, "tictactoe_ws_connects_total", "WebSocket connections accepted"),
+            ws_disconnects: counter(&registry, "tictactoe_ws_disconnects_total", "WebSocket connections closed"),
+            queue,
+            registry,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub async fn metrics(State(state): State<SharedState>) -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], state.metrics.render())
+}
@@ -0,0 +1,133 @@
+use std::net::IpAddr;
+use std::time::Instant;
+use dashmap::DashMap;
+
+/// Token bucket parameters: up to `burst` events at once, refilled at `per_second`.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        Self { limit, tokens: limit.burst as f64, last_refill: Instant::now() }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        self.last_refill = now;
+    }
+
+    // Take one token; false means the caller is over the limit.
+    pub fn try_take(&mut self) -> bool {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.limit.burst as f64
+    }
+}
+
+/// One token bucket per client IP, shared by all of that IP's connections.
+pub struct IpRateLimiter {
+    limit: RateLimit,
+    buckets: DashMap<IpAddr, TokenBucket>,
+}
+
+impl IpRateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self { limit, buckets: DashMap::new() }
+    }
+
+    pub fn try_take(&self, ip: IpAddr) -> bool {
+        self.try_take_at(ip, Instant::now())
+    }
+
+    fn try_take_at(&self, ip: IpAddr, now: Instant) -> bool {
+        self.buckets.entry(ip).or_insert_with(|| TokenBucket::new(self.limit)).try_take_at(now)
+    }
+
+    // Forget IPs whose bucket has refilled completely; they'd start from a full bucket anyway.
+    pub fn prune(&self) {
+        self.prune_at(Instant::now());
+    }
+
+    fn prune_at(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| !bucket.is_full(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const LIMIT: RateLimit = RateLimit { burst: 3, per_second: 2.0 };
+
+    #[test]
+    fn bucket_allows_a_burst_then_refuses() {
+        let mut bucket = TokenBucket::new(LIMIT);
+        let now = Instant::now();
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(bucket.try_take_at(now));
+        assert!(!bucket.try_take_at(now));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(LIMIT);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(bucket.try_take_at(start));
+        }
+        // Half a token after 250 ms at 2 per second
+        assert!(!bucket.try_take_at(start + Duration::from_millis(250)));
+        assert!(bucket.try_take_at(start + Duration::from_millis(500)));
+        assert!(!bucket.try_take_at(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn bucket_never_holds_more_than_the_burst() {
+        let mut bucket = TokenBucket::new(LIMIT);
+        let later = Instant::now() + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(bucket.try_take_at(later));
+        }
+        assert!(!bucket.try_take_at(later));
+    }
+
+    #[test]
+    fn prune_forgets_only_refilled_ips() {
+        let limiter = IpRateLimiter::new(LIMIT);
+        let (idle, busy): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let start = Instant::now();
+        assert!(limiter.try_take_at(idle, start));
+        for _ in 0..3 {
+            assert!(limiter.try_take_at(busy, start + Duration::from_secs(1)));
+        }
+        // 1.5 s in: idle has refilled its one token, busy has only got one of its three back
+        limiter.prune_at(start + Duration::from_millis(1500));
+        assert!(!limiter.buckets.contains_key(&idle));
+        assert!(limiter.buckets.contains_key(&busy));
+    }
+}
@@ -0,0 +1,621 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, info, info_span, Instrument};
+use crate::auth::Player;
+use crate::server::{Connection, ConnectionId, Room, SharedState, ROOM_CAPACITY};
+use crate::room::{RoomStateResponse, PlayerMark, RoomResponse, ResponseType, GameStateResponse, MoveMadeResponse, AckResponse, ErrorResponse, LatencyResponse, PlayerLatency, NoticeResponse};
+use crate::room::requests::Action;
+use crate::room::protocol::{PROTOCOL_VERSION, DELTA_UPDATES_VERSION, LATENCY_REPORTS_VERSION, NOTICES_VERSION};
+use crate::room::admin::{PlayerSnapshot, RoomSnapshot};
+use crate::room::outbox::OutboxSender;
+
+// Commands waiting for a busy room are buffered up to this depth before senders are made to wait.
+const ROOM_COMMAND_BUFFER: usize = 64;
+
+/// Typed commands applied one at a time by a room's actor task, so every state transition is serialized.
+pub enum RoomCommand {
+    Join {
+        tx: OutboxSender,
+        protocol_version: u32,
+        player: Option<Player>,
+        reply: oneshot::Sender<JoinOutcome>,
+    },
+    Leave { connection_id: ConnectionId },
+    StartGame { connection_id: ConnectionId, request_id: Option<String> },
+    MakeMove { connection_id: ConnectionId, x: u8, y: u8, request_id: Option<String> },
+    RestartGame { connection_id: ConnectionId, request_id: Option<String> },
+    Resync { connection_id: ConnectionId, request_id: Option<String> },
+    Latency { connection_id: ConnectionId, latency_ms: u64 },
+    // Admin API
+    Inspect { reply: oneshot::Sender<RoomSnapshot> },
+    Notice { message: String },
+    Kick { connection_id: ConnectionId, reason: String, reply: oneshot::Sender<bool> },
+    Close { reason: String },
+}
+
+pub enum JoinOutcome {
+    Joined { connection_id: ConnectionId, mark: PlayerMark },
+    Full { num_connections: usize },
+}
+
+#[derive(Clone)]
+pub struct RoomHandle {
+    tx: mpsc::Sender<RoomCommand>,
+    // Seated players, published by the actor after every command so it can be read without a round trip
+    players: Arc<AtomicUsize>,
+}
+
+impl RoomHandle {
+    // Returns false when the room's actor has already shut down.
+    pub async fn send(&self, command: RoomCommand) -> bool {
+        self.tx.send(command).await.is_ok()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    pub fn players(&self) -> usize {
+        self.players.load(Ordering::Relaxed)
+    }
+}
+
+// Find (or spawn) the room's actor and register a new connection with it. If the actor shuts down between
+// the lookup and the join (its last player just left), the join is retried against a fresh room. While the
+// server is draining only existing rooms can be joined.
+pub async fn join(
+    state: &SharedState,
+    room_id: &str,
+    tx: OutboxSender,
+    protocol_version: u32,
+    player: Option<Player>,
+) -> Result<(RoomHandle, JoinOutcome), &'static str> {
+    loop {
+        // The map shard is only held long enough to clone the handle, never across an await
+        let handle = if state.is_draining() {
+            match state.rooms.get(room_id) {
+                Some(handle) => handle.clone(),
+                None => return Err("server_draining"),
+            }
+        } else {
+            state.rooms
+                .entry(room_id.to_string())
+                .or_insert_with(|| RoomActor::spawn(state.clone(), room_id.to_string()))
+                .clone()
+        };
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let command = RoomCommand::Join { tx: tx.clone(), protocol_version, player: player.clone(), reply: reply_tx };
+        if handle.send(command).await {
+            if let Ok(outcome) = reply_rx.await { return Ok((handle, outcome)); }
+        }
+        state.rooms.remove_if(room_id, |_, h| h.is_closed());
+    }
+}
+
+// Helper: build serialized board for GameStateResponse
+fn serialize_board(board: &[[Option<PlayerMark>;3];3]) -> Vec<Vec<Option<String>>> {
+    board.iter().map(|row| {
+        row.iter().map(|cell| cell.map(|m| m.to_string())).collect()
+    }).collect()
+}
+
+fn build_game_state(room_id: &str, room: &Room) -> GameStateResponse {
+    GameStateResponse {
+        room_id: room_id.to_string(),
+        board: serialize_board(&room.game.board),
+        current_turn: if room.started && room.game.winner.is_none() { Some(room.game.current_turn.to_string()) } else { None },
+        winner: room.game.winner.map(|w| w.to_string()),
+        started: room.started,
+        moves_count: room.game.moves_count,
+        version: room.version,
+    }
+}
+
+pub(crate) fn error_payload(room_id: &str, code: &str, request_id: Option<String>) -> RoomResponse {
+    let err = ErrorResponse { room_id: room_id.to_string(), code: code.to_string(), message: code.to_string(), request_id };
+    RoomResponse { response_type: ResponseType::Error, response: err.to_json_value() }
+}
+
+pub(crate) fn room_state_payload(room_id: &str, num_connections: usize, message: String, success: bool, my_mark: PlayerMark) -> RoomResponse {
+    let room_state_response = RoomStateResponse {
+        room_id: room_id.to_string(),
+        num_connections,
+        message,
+        success,
+        my_mark: my_mark.to_string(),
+        protocol_version: PROTOCOL_VERSION,
+    };
+    RoomResponse { response_type: ResponseType::RoomState, response: room_state_response.to_json_value() }
+}
+
+struct RoomActor {
+    room_id: String,
+    room: Room,
+    state: SharedState,
+    players: Arc<AtomicUsize>,
+}
+
+impl RoomActor {
+    fn spawn(state: SharedState, room_id: String) -> RoomHandle {
+        let (tx, rx) = mpsc::channel(ROOM_COMMAND_BUFFER);
+        state.metrics.active_rooms.inc();
+        // A root span: the room outlives the connection whose join happened to spawn it
+        let span = info_span!(parent: None, "room", room_id = %room_id);
+        let players = Arc::new(AtomicUsize::new(0));
+        let actor = RoomActor { room_id, room: Room::new(), state, players: players.clone() };
+        tokio::spawn(actor.run(rx).instrument(span));
+        RoomHandle { tx, players }
+    }
+
+    async fn run(mut self, mut rx: mpsc::Receiver<RoomCommand>) {
+        info!("room opened");
+        while let Some(command) = rx.recv().await {
+            self.handle(command);
+            self.players.store(self.room.connections.len(), Ordering::Relaxed);
+            if self.room.connections.is_empty() {
+                // Last player left: stop accepting commands and unregister. Joins still queued are dropped
+                // with the receiver, which makes their senders retry against a fresh room.
+                rx.close();
+                self.state.rooms.remove_if(&self.room_id, |_, h| h.is_closed());
+                break;
+            }
+        }
+        self.state.metrics.active_rooms.dec();
+        info!("room closed");
+    }
+
+    fn handle(&mut self, command: RoomCommand) {
+        match command {
+            RoomCommand::Join { tx, protocol_version, player, reply } => self.join(tx, protocol_version, player, reply),
+            RoomCommand::Leave { connection_id } => self.cleanup_dead_connections(vec![connection_id]),
+            RoomCommand::StartGame { connection_id, request_id } => {
+                // Only seated connections control the game; a command can race its sender leaving
+                if !self.room.connections.contains_key(&connection_id) { return; }
+                match self.room.start_game() {
+                    Ok(()) => {
+                        info!(connection_id, version = self.room.version, "game started");
+                        self.broadcast_game_state();
+                        self.send_ack(connection_id, request_id, Action::StartGame);
+                    }
+                    Err(code) => self.send_error(connection_id, code, request_id),
+                }
+            }
+            RoomCommand::MakeMove { connection_id, x, y, request_id } => {
+                let Some(mark) = self.room.connections.get(&connection_id).map(|c| c.mark) else { return; };
+                match self.room.make_move(mark, x, y) {
+                    Ok(()) => {
+                        debug!(connection_id, mark = ?mark, x, y, version = self.room.version, "move applied");
+                        self.record_move();
+                        self.broadcast_move(x, y, mark);
+                        self.send_ack(connection_id, request_id, Action::MakeMove);
+                    }
+                    Err(code) => self.send_error(connection_id, code, request_id),
+                }
+            }
+            RoomCommand::RestartGame { connection_id, request_id } => {
+                // Only seated connections control the game; a command can race its sender leaving
+                if !self.room.connections.contains_key(&connection_id) { return; }
+                match self.room.restart_game() {
+                    Ok(()) => {
+                        info!(connection_id, version = self.room.version, "game restarted");
+                        self.broadcast_game_state();
+                        self.send_ack(connection_id, request_id, Action::RestartGame);
+                    }
+                    Err(code) => self.send_error(connection_id, code, request_id),
+                }
+            }
+            RoomCommand::Resync { connection_id, request_id } => {
+                self.send_game_state(connection_id);
+                self.send_ack(connection_id, request_id, Action::Resync);
+            }
+            RoomCommand::Latency { connection_id, latency_ms } => self.report_latency(connection_id, latency_ms),
+            RoomCommand::Inspect { reply } => { let _ = reply.send(self.snapshot()); }
+            RoomCommand::Notice { message } => self.broadcast_notice(&message),
+            RoomCommand::Kick { connection_id, reason, reply } => { let _ = reply.send(self.kick(connection_id, &reason)); }
+            RoomCommand::Close { reason } => self.close(&reason),
+        }
+    }
+
+    fn snapshot(&self) -> RoomSnapshot {
+        let mut players: Vec<PlayerSnapshot> = self.room.connections.iter()
+            .map(|(&connection_id, conn)| PlayerSnapshot {
+                connection_id,
+                player_id: conn.player.as_ref().map(|p| p.id.clone()),
+                display_name: conn.player.as_ref().map(|p| p.display_name.clone()),
+                my_mark: conn.mark.to_string(),
+                protocol_version: conn.protocol_version,
+                latency_ms: conn.latency_ms,
+            })
+            .collect();
+        players.sort_by_key(|p| p.connection_id);
+        RoomSnapshot { room_id: self.room_id.clone(), players, game: build_game_state(&self.room_id, &self.room) }
+    }
+
+    fn notice_payload(&self, message: &str) -> RoomResponse {
+        let notice = NoticeResponse { room_id: self.room_id.clone(), message: message.to_string() };
+        RoomResponse { response_type: ResponseType::Notice, response: notice.to_json_value() }
+    }
+
+    fn broadcast_notice(&mut self, message: &str) {
+        let payload = self.notice_payload(message);
+        self.broadcast(|conn| (conn.protocol_version >= NOTICES_VERSION).then(|| payload.clone()));
+    }
+
+    // Tell the connection why, let its queued messages flush, and free its seat. False if it isn't in this room.
+    fn kick(&mut self, connection_id: ConnectionId, reason: &str) -> bool {
+        let payload = self.notice_payload(reason);
+        let Some(conn) = self.room.connections.get(&connection_id) else { return false; };
+        if conn.protocol_version >= NOTICES_VERSION { let _ = conn.tx.send(payload); }
+        conn.tx.finish();
+        info!(connection_id, reason, "connection kicked");
+        self.cleanup_dead_connections(vec![connection_id]);
+        true
+    }
+
+    // Notify and disconnect everyone; the actor then shuts down like any room whose last player left.
+    fn close(&mut self, reason: &str) {
+        info!(reason, "room force-closed");
+        self.broadcast_notice(reason);
+        for (_, conn) in self.room.connections.drain() {
+            conn.tx.finish();
+            self.state.metrics.connected_players.dec();
+        }
+    }
+
+    // A verified player joining again (another tab or device, or a reconnect the server hasn't noticed is one
+    // yet) replaces their old connection instead of taking the second seat. The old socket is told why and
+    // closed; no leave is broadcast since the player never really left.
+    fn take_over(&mut self, player: &Player) {
+        let previous = self.room.connections.iter()
+            .find(|(_, conn)| conn.player.as_ref().is_some_and(|p| p.id == player.id))
+            .map(|(&cid, _)| cid);
+        let Some(previous) = previous else { return; };
+        let payload = self.notice_payload("Signed in from another connection");
+        if let Some(conn) = self.room.connections.remove(&previous) {
+            if conn.protocol_version >= NOTICES_VERSION { let _ = conn.tx.send(payload); }
+            conn.tx.finish();
+            self.state.metrics.connected_players.dec();
+            info!(connection_id = previous, player_id = %player.id, "connection taken over");
+        }
+    }
+
+    fn join(&mut self, tx: OutboxSender, protocol_version: u32, player: Option<Player>, reply: oneshot::Sender<JoinOutcome>) {
+        if let Some(player) = &player { self.take_over(player); }
+        let current_count = self.room.connections.len();
+        if current_count >= ROOM_CAPACITY {
+            info!(num_connections = current_count, "join rejected: room is full");
+            let _ = reply.send(JoinOutcome::Full { num_connections: current_count });
+            return;
+        }
+
+        let connection_id = self.state.next_connection_id();
+        let mark = self.room.mark_for(player.as_ref());
+        let player_id = player.as_ref().map(|p| p.id.clone());
+        self.room.connections.insert(connection_id, Connection { tx, mark, protocol_version, latency_ms: None, player });
+        if reply.send(JoinOutcome::Joined { connection_id, mark }).is_err() {
+            // The joining socket went away while waiting for us
+            self.room.connections.remove(&connection_id);
+            return;
+        }
+        if let Some(player_id) = &player_id { self.room.seats.insert(player_id.clone(), mark); }
+        self.state.metrics.connected_players.inc();
+        info!(connection_id, mark = ?mark, player_id, protocol_version, num_connections = self.room.connections.len(), "player joined");
+
+        // Broadcast join notification
+        let room_id = self.room_id.clone();
+        let num_connections = self.room.connections.len();
+        self.broadcast(|conn| Some(room_state_payload(&room_id, num_connections, "Someone joined the room".to_string(), true, conn.mark)));
+
+        // If the room just filled up, auto-start game; otherwise give the joiner a full snapshot of the current state
+        if self.room.connections.len() == ROOM_CAPACITY && !self.room.started && self.room.start_game().is_ok() {
+            info!(version = self.room.version, "game auto-started");
+            self.broadcast_game_state();
+        } else {
+            self.send_game_state(connection_id);
+        }
+    }
+
+    // Remove connections whose socket is gone and tell the remaining players who left.
+    fn cleanup_dead_connections(&mut self, dead_connections: Vec<ConnectionId>) {
+        for cid in dead_connections {
+            if let Some(conn) = self.room.connections.remove(&cid) {
+                self.state.metrics.connected_players.dec();
+                let room_id = self.room_id.clone();
+                let num_connections = self.room.connections.len();
+                info!(connection_id = cid, mark = ?conn.mark, num_connections, "player left");
+                let message = format!("Player {} left the room", conn.mark);
+                self.broadcast(|other| Some(room_state_payload(&room_id, num_connections, message.clone(), true, other.mark)));
+            }
+        }
+    }
+
+    // Send a per-recipient message to everyone in the room; `build` returns None to skip a connection.
+    fn broadcast(&mut self, build: impl Fn(&Connection) -> Option<RoomResponse>) {
+        let mut dead = Vec::new();
+        for (&cid, conn) in &self.room.connections {
+            if let Some(payload) = build(conn) {
+                if conn.tx.send(payload).is_err() { dead.push(cid); }
+            }
+        }
+        if !dead.is_empty() {
+            debug!(connections = ?dead, "outbox closed, cleaning up");
+            self.cleanup_dead_connections(dead);
+        }
+    }
+
+    fn send_to_connection(&mut self, connection_id: ConnectionId, payload: RoomResponse) {
+        let alive = match self.room.connections.get(&connection_id) {
+            Some(conn) => conn.tx.send(payload).is_ok(),
+            None => return,
+        };
+        if !alive {
+            debug!(connection_id, "outbox closed, cleaning up");
+            self.cleanup_dead_connections(vec![connection_id]);
+        }
+    }
+
+    fn game_state_payload(&self) -> RoomResponse {
+        RoomResponse { response_type: ResponseType::GameState, response: build_game_state(&self.room_id, &self.room).to_json_value() }
+    }
+
+    fn broadcast_game_state(&mut self) {
+        let payload = self.game_state_payload();
+        self.broadcast(|_| Some(payload.clone()));
+    }
+
+    // Broadcast the delta of a successful move: `move_made` to clients that understand it, a full snapshot to older ones.
+    fn broadcast_move(&mut self, x: u8, y: u8, mark: PlayerMark) {
+        let gs = build_game_state(&self.room_id, &self.room);
+        let mm = MoveMadeResponse {
+            room_id: self.room_id.clone(),
+            x,
+            y,
+            mark: mark.to_string(),
+            version: self.room.version,
+            current_turn: gs.current_turn.clone(),
+            winner: gs.winner.clone(),
+            moves_count: self.room.game.moves_count,
+        };
+        let delta_payload = RoomResponse { response_type: ResponseType::MoveMade, response: mm.to_json_value()};
+        let full_payload = RoomResponse { response_type: ResponseType::GameState, response: gs.to_json_value()};
+        self.broadcast(|conn| {
+            Some(if conn.protocol_version >= DELTA_UPDATES_VERSION { delta_payload.clone() } else { full_payload.clone() })
+        });
+    }
+
+    fn record_move(&self) {
+        let metrics = &self.state.metrics;
+        metrics.moves.inc();
+        if self.room.is_finished() {
+            let outcome = match self.room.game.winner {
+                Some(PlayerMark::X) => "x_won",
+                Some(PlayerMark::O) => "o_won",
+                None => "draw",
+            };
+            metrics.games_finished.with_label_values(&[outcome]).inc();
+            info!(outcome, moves = self.room.game.moves_count, "game finished");
+        }
+    }
+
+    // Full snapshot for a single connection, used on join and when a client reports a version gap.
+    fn send_game_state(&mut self, connection_id: ConnectionId) {
+        let payload = self.game_state_payload();
+        self.send_to_connection(connection_id, payload);
+    }
+
+    fn send_error(&mut self, connection_id: ConnectionId, code: &str, request_id: Option<String>) {
+        self.state.metrics.errors.with_label_values(&[code]).inc();
+        debug!(connection_id, code, "action rejected");
+        let payload = error_payload(&self.room_id, code, request_id);
+        self.send_to_connection(connection_id, payload);
+    }
+
+    // Acknowledge a successful action; a no-op when the client didn't supply a request id.
+    fn send_ack(&mut self, connection_id: ConnectionId, request_id: Option<String>, action: Action) {
+        let Some(request_id) = request_id else { return; };
+        let ack = AckResponse { room_id: self.room_id.clone(), request_id, action, version: self.room.version };
+        let payload = RoomResponse { response_type: ResponseType::Ack, response: ack.to_json_value()};
+        self.send_to_connection(connection_id, payload);
+    }
+
+    // Record a player's measured round-trip time and report everyone's latency to clients that understand it.
+    fn report_latency(&mut self, connection_id: ConnectionId, latency_ms: u64) {
+        let Some(conn) = self.room.connections.get_mut(&connection_id) else { return; };
+        conn.latency_ms = Some(latency_ms);
+        let players: Vec<PlayerLatency> = self.room.connections.values()
+            .map(|conn| PlayerLatency { my_mark: conn.mark.to_string(), latency_ms: conn.latency_ms })
+            .collect();
+        let latency = LatencyResponse { room_id: self.room_id.clone(), players };
+        let payload = RoomResponse { response_type: ResponseType::Latency, response: latency.to_json_value()};
+        self.broadcast(|conn| (conn.protocol_version >= LATENCY_REPORTS_VERSION).then(|| payload.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::server::{AppState, ServerConfig};
+    use crate::room::outbox::{self, OutboxReceiver};
+    use crate::room::protocol::DELTA_UPDATES_VERSION;
+
+    fn state() -> SharedState {
+        Arc::new(AppState::new(ServerConfig::default()))
+    }
+
+    fn verified(id: &str) -> Option<Player> {
+        Some(Player { id: id.to_string(), display_name: id.to_string() })
+    }
+
+    struct TestClient {
+        room: RoomHandle,
+        outcome: JoinOutcome,
+        rx: OutboxReceiver,
+    }
+
+    impl TestClient {
+        fn connection_id(&self) -> ConnectionId {
+            match self.outcome {
+                JoinOutcome::Joined { connection_id, .. } => connection_id,
+                JoinOutcome::Full { .. } => panic!("not seated"),
+            }
+        }
+
+        async fn next(&mut self) -> Option<RoomResponse> {
+            tokio::time::timeout(Duration::from_secs(1), self.rx.recv()).await.expect("no message from the room")
+        }
+
+        // Skip ahead to the next message of `response_type`
+        async fn next_of(&mut self, response_type: ResponseType) -> serde_json::Value {
+            loop {
+                let message = self.next().await.expect("connection closed");
+                if message.response_type == response_type { return message.response; }
+            }
+        }
+    }
+
+    async fn connect(state: &SharedState, room_id: &str, protocol_version: u32, player: Option<Player>) -> Result<TestClient, &'static str> {
+        let (tx, rx) = outbox::channel(64, state.config.overflow_policy, state.metrics.queue.clone());
+        let (room, outcome) = join(state, room_id, tx, protocol_version, player).await?;
+        Ok(TestClient { room, outcome, rx })
+    }
+
+    // The actor unregisters itself after handling the last leave
+    async fn wait_until_unregistered(state: &SharedState, room_id: &str) {
+        for _ in 0..100 {
+            if !state.rooms.contains_key(room_id) { return; }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("room {room_id} still registered");
+    }
+
+    #[tokio::test]
+    async fn second_join_starts_the_game_and_a_third_is_turned_away() {
+        let state = state();
+        let mut x = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        let mut o = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        assert!(matches!(x.outcome, JoinOutcome::Joined { mark: PlayerMark::X, .. }));
+        assert!(matches!(o.outcome, JoinOutcome::Joined { mark: PlayerMark::O, .. }));
+        assert_eq!(x.next_of(ResponseType::RoomState).await["num_connections"], 1);
+        assert_eq!(x.next_of(ResponseType::GameState).await["started"], false);
+        assert_eq!(x.next_of(ResponseType::RoomState).await["num_connections"], 2);
+        assert_eq!(x.next_of(ResponseType::GameState).await["started"], true);
+        assert_eq!(o.next_of(ResponseType::GameState).await["started"], true);
+
+        let third = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        assert!(matches!(third.outcome, JoinOutcome::Full { num_connections: 2 }));
+        assert_eq!(x.room.players(), 2);
+    }
+
+    #[tokio::test]
+    async fn moves_reach_old_clients_as_game_state_and_new_ones_as_move_made() {
+        let state = state();
+        let mut x = connect(&state, "fun", DELTA_UPDATES_VERSION - 1, None).await.unwrap();
+        let mut o = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        x.next_of(ResponseType::RoomState).await;
+        o.next_of(ResponseType::GameState).await;
+        while x.next_of(ResponseType::GameState).await["started"] == false {}
+
+        let command = RoomCommand::MakeMove { connection_id: x.connection_id(), x: 1, y: 1, request_id: Some("m1".to_string()) };
+        assert!(x.room.send(command).await);
+        let full = x.next().await.unwrap();
+        assert_eq!(full.response_type, ResponseType::GameState);
+        assert_eq!(full.response["board"][1][1], "x");
+        let ack = x.next().await.unwrap();
+        assert_eq!(ack.response_type, ResponseType::Ack);
+        assert_eq!(ack.response["request_id"], "m1");
+        assert_eq!(ack.response["version"], full.response["version"]);
+
+        let delta = o.next().await.unwrap();
+        assert_eq!(delta.response_type, ResponseType::MoveMade);
+        assert_eq!(delta.response["mark"], "x");
+        assert_eq!(delta.response["version"], full.response["version"]);
+    }
+
+    #[tokio::test]
+    async fn rejected_actions_echo_the_request_id() {
+        let state = state();
+        let mut x = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        let mut o = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        o.next_of(ResponseType::GameState).await;
+
+        let command = RoomCommand::MakeMove { connection_id: o.connection_id(), x: 0, y: 0, request_id: Some("early".to_string()) };
+        o.room.send(command).await;
+        let error = o.next_of(ResponseType::Error).await;
+        assert_eq!((error["code"].clone(), error["request_id"].clone()), ("not_your_turn".into(), "early".into()));
+
+        // Without a request id the error carries none, and a success isn't acknowledged
+        x.room.send(RoomCommand::StartGame { connection_id: x.connection_id(), request_id: None }).await;
+        let error = x.next_of(ResponseType::Error).await;
+        assert_eq!((error["code"].clone(), error["request_id"].clone()), ("game_already_started".into(), serde_json::Value::Null));
+        x.room.send(RoomCommand::Resync { connection_id: x.connection_id(), request_id: None }).await;
+        x.next_of(ResponseType::GameState).await;
+        x.room.send(RoomCommand::Resync { connection_id: x.connection_id(), request_id: Some("r".to_string()) }).await;
+        x.next_of(ResponseType::GameState).await;
+        assert_eq!(x.next().await.unwrap().response_type, ResponseType::Ack);
+    }
+
+    #[tokio::test]
+    async fn a_verified_player_joining_again_takes_over_their_seat() {
+        let state = state();
+        let mut old = connect(&state, "fun", PROTOCOL_VERSION, verified("alice")).await.unwrap();
+        let mut bob = connect(&state, "fun", PROTOCOL_VERSION, verified("bob")).await.unwrap();
+        let new = connect(&state, "fun", PROTOCOL_VERSION, verified("alice")).await.unwrap();
+        assert!(matches!(new.outcome, JoinOutcome::Joined { mark: PlayerMark::X, .. }));
+        assert_ne!(new.connection_id(), old.connection_id());
+
+        // The old connection is told why, then closed once that is flushed
+        assert_eq!(old.next_of(ResponseType::Notice).await["message"], "Signed in from another connection");
+        assert!(old.next().await.is_none());
+        // Bob sees the new connection join, not alice leaving
+        assert_eq!(bob.next_of(ResponseType::RoomState).await["num_connections"], 2);
+        let rejoined = bob.next_of(ResponseType::RoomState).await;
+        assert_eq!((rejoined["message"].clone(), rejoined["num_connections"].clone()), ("Someone joined the room".into(), 2.into()));
+        assert_eq!(new.room.players(), 2);
+    }
+
+    #[tokio::test]
+    async fn the_last_leave_closes_and_unregisters_the_room() {
+        let state = state();
+        let x = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        let mut o = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        o.next_of(ResponseType::GameState).await;
+
+        x.room.send(RoomCommand::Leave { connection_id: x.connection_id() }).await;
+        let left = o.next_of(ResponseType::RoomState).await;
+        assert_eq!((left["message"].clone(), left["num_connections"].clone()), ("Player x left the room".into(), 1.into()));
+        assert!(state.rooms.contains_key("fun"));
+
+        o.room.send(RoomCommand::Leave { connection_id: o.connection_id() }).await;
+        wait_until_unregistered(&state, "fun").await;
+        assert!(o.room.is_closed());
+        assert_eq!(state.metrics.active_rooms.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_join_racing_the_room_shutting_down_retries_against_a_fresh_room() {
+        let state = state();
+        // A handle whose actor has already stopped, as left behind by a room that just emptied
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        state.rooms.insert("fun".to_string(), RoomHandle { tx, players: Arc::new(AtomicUsize::new(0)) });
+
+        let mut x = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        assert!(matches!(x.outcome, JoinOutcome::Joined { mark: PlayerMark::X, .. }));
+        assert!(!x.room.is_closed());
+        assert_eq!(x.next_of(ResponseType::RoomState).await["num_connections"], 1);
+    }
+
+    #[tokio::test]
+    async fn a_draining_server_only_admits_players_to_existing_rooms() {
+        let state = state();
+        let _x = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        state.draining.store(true, Ordering::Relaxed);
+
+        assert_eq!(connect(&state, "new", PROTOCOL_VERSION, None).await.err(), Some("server_draining"));
+        assert!(!state.rooms.contains_key("new"));
+        let o = connect(&state, "fun", PROTOCOL_VERSION, None).await.unwrap();
+        assert!(matches!(o.outcome, JoinOutcome::Joined { mark: PlayerMark::O, .. }));
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::atomic::Ordering;
+use axum::extract::{Path, Request, State};
+use axum::http::StatusCode;
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::oneshot;
+use tracing::info;
+use crate::auth::bearer_token;
+use crate::room::actor::RoomCommand;
+use crate::room::GameStateResponse;
+use crate::server::{ConnectionId, SharedState};
+
+#[derive(Serialize)]
+pub struct PlayerSnapshot {
+    pub connection_id: ConnectionId,
+    // Verified identity, absent for anonymous players
+    pub player_id: Option<String>,
+    pub display_name: Option<String>,
+    pub my_mark: String,
+    pub protocol_version: u32,
+    pub latency_ms: Option<u64>,
+}
+
+// Everything an operator needs to know about one room, as reported by its actor.
+#[derive(Serialize)]
+pub struct RoomSnapshot {
+    pub room_id: String,
+    pub players: Vec<PlayerSnapshot>,
+    pub game: GameStateResponse,
+}
+
+#[derive(Deserialize)]
+pub struct ReasonBody {
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NoticeBody {
+    pub message: String,
+}
+
+#[derive(Deserialize)]
+pub struct DrainBody {
+    pub enabled: bool,
+}
+
+// Routes nested under `/admin`, all requiring `Authorization: Bearer <admin_token>`.
+pub fn admin_routes(state: SharedState) -> Router<SharedState> {
+    Router::new()
+        .route("/rooms", get(list_rooms))
+        .route("/rooms/{room_id}", delete(close_room))
+        .route("/rooms/{room_id}/connections/{connection_id}", delete(kick_connection))
+        .route("/notice", post(broadcast_notice))
+        .route("/drain", get(drain_status).put(set_drain))
+        .route_layer(middleware::from_fn_with_state(state, require_admin_token))
+}
+
+// Compare without short-circuiting so response timing doesn't reveal how much of the token matched.
+fn token_matches(given: &[u8], expected: &[u8]) -> bool {
+    given.len() == expected.len() && given.iter().zip(expected).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn require_admin_token(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let Some(expected) = state.config.admin_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    match bearer_token(request.headers()) {
+        Some(given) if token_matches(given.as_bytes(), expected.as_bytes()) => next.run(request).await,
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+fn room_not_found() -> Response {
+    (StatusCode::NOT_FOUND, Json(json!({ "code": "room_not_found" }))).into_response()
+}
+
+async fn list_rooms(State(state): State<SharedState>) -> Json<Vec<RoomSnapshot>> {
+    // Clone the handles first so no map shard is held while waiting on actors
+    let handles: Vec<_> = state.rooms.iter().map(|entry| entry.value().clone()).collect();
+    let mut rooms = Vec::with_capacity(handles.len());
+    for handle in handles {
+        let (reply, snapshot) = oneshot::channel();
+        // A room that shut down in the meantime is simply left out
+        if handle.send(RoomCommand::Inspect { reply }).await {
+            if let Ok(snapshot) = snapshot.await { rooms.push(snapshot); }
+        }
+    }
+    rooms.sort_by(|a, b| a.room_id.cmp(&b.room_id));
+    Json(rooms)
+}
+
+async fn close_room(State(state): State<SharedState>, Path(room_id): Path<String>, body: Option<Json<ReasonBody>>) -> Response {
+    let Some(handle) = state.rooms.get(&room_id).map(|h| h.clone()) else { return room_not_found(); };
+    let reason = body.and_then(|Json(b)| b.reason).unwrap_or_else(|| "Room closed by the server".to_string());
+    if !handle.send(RoomCommand::Close { reason }).await { return room_not_found(); }
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn kick_connection(
+    State(state): State<SharedState>,
+    Path((room_id, connection_id)): Path<(String, ConnectionId)>,
+    body: Option<Json<ReasonBody>>,
+) -> Response {
+    let Some(handle) = state.rooms.get(&room_id).map(|h| h.clone()) else { return room_not_found(); };
+    let reason = body.and_then(|Json(b)| b.reason).unwrap_or_else(|| "Removed by the server".to_string());
+    let (reply, kicked) = oneshot::channel();
+    if !handle.send(RoomCommand::Kick { connection_id, reason, reply }).await { return room_not_found(); }
+    match kicked.await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        _ => (StatusCode::NOT_FOUND, Json(json!({ "code": "connection_not_found" }))).into_response(),
+    }
+}
+
+async fn broadcast_notice(State(state): State<SharedState>, Json(body): Json<NoticeBody>) -> Json<serde_json::Value> {
+    let handles: Vec<_> = state.rooms.iter().map(|entry| entry.value().clone()).collect();
+    let mut rooms = 0;
+    for handle in handles {
+        if handle.send(RoomCommand::Notice { message: body.message.clone() }).await { rooms += 1; }
+    }
+    info!(rooms, message = %body.message, "notice broadcast");
+    Json(json!({ "rooms": rooms }))
+}
+
+fn drain_state(state: &SharedState) -> Json<serde_json::Value> {
+    Json(json!({ "draining": state.is_draining(), "active_rooms": state.rooms.len() }))
+}
+
+async fn drain_status(State(state): State<SharedState>) -> Json<serde_json::Value> {
+    drain_state(&state)
+}
+
+async fn set_drain(State(state): State<SharedState>, Json(body): Json<DrainBody>) -> Json<serde_json::Value> {
+    state.draining.store(body.enabled, Ordering::Relaxed);
+    info!(enabled = body.enabled, "drain mode changed");
+    drain_state(&state)
+}
@@ -0,0 +1,182 @@
+use axum::extract::ws::Message;
+use axum::http::HeaderValue;
+use crate::room::requests::Payload;
+use crate::room::RoomResponse;
+
+pub const SUBPROTOCOL_MSGPACK: &str = "tictactoe.msgpack";
+pub const SUBPROTOCOL_CBOR: &str = "tictactoe.cbor";
+pub const SUBPROTOCOL_JSON: &str = "tictactoe.json";
+
+// Offered during the handshake in order of server preference: binary encodings win when a client lists several.
+pub const SUBPROTOCOLS: [&str; 3] = [SUBPROTOCOL_MSGPACK, SUBPROTOCOL_CBOR, SUBPROTOCOL_JSON];
+
+/// Wire encoding negotiated for a single connection via the WebSocket subprotocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    InvalidJson,
+    InvalidPayload,
+}
+
+impl DecodeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DecodeError::InvalidJson => "invalid_json",
+            DecodeError::InvalidPayload => "invalid_payload",
+        }
+    }
+}
+
+impl Encoding {
+    // No subprotocol (or an unknown one) keeps the original JSON text protocol.
+    pub fn from_subprotocol(protocol: Option<&HeaderValue>) -> Self {
+        match protocol.and_then(|p| p.to_str().ok()) {
+            Some(SUBPROTOCOL_MSGPACK) => Encoding::MessagePack,
+            Some(SUBPROTOCOL_CBOR) => Encoding::Cbor,
+            _ => Encoding::Json,
+        }
+    }
+
+    pub fn encode(&self, response: &RoomResponse) -> Message {
+        match self {
+            Encoding::Json => {
+                let json = serde_json::to_string(response).unwrap_or_else(|_| "{}".to_string());
+                Message::Text(json.into())
+            }
+            Encoding::MessagePack => {
+                let bytes = rmp_serde::to_vec_named(response).unwrap_or_default();
+                Message::Binary(bytes.into())
+            }
+            Encoding::Cbor => {
+                let mut bytes = Vec::new();
+                let _ = ciborium::into_writer(response, &mut bytes);
+                Message::Binary(bytes.into())
+            }
+        }
+    }
+
+    // JSON connections take text frames, binary ones binary frames in the negotiated encoding; a frame of the
+    // other kind is rejected rather than guessed at. Returns None for frames that carry no request (ping/pong/close).
+    pub fn decode(&self, message: &Message) -> Option<Result<Payload, DecodeError>> {
+        match (self, message) {
+            (Encoding::Json, Message::Text(text)) => Some(serde_json::from_str(text).map_err(|_| DecodeError::InvalidJson)),
+            (Encoding::MessagePack, Message::Binary(bytes)) => {
+                Some(rmp_serde::from_slice(bytes).map_err(|_| DecodeError::InvalidPayload))
+            }
+            (Encoding::Cbor, Message::Binary(bytes)) => {
+                Some(ciborium::from_reader(bytes.as_ref()).map_err(|_| DecodeError::InvalidPayload))
+            }
+            (_, Message::Text(_) | Message::Binary(_)) => Some(Err(DecodeError::InvalidPayload)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use crate::room::requests::{Action, MakeMovePayload};
+    use crate::room::{AckResponse, ResponseType};
+
+    const ENCODINGS: [Encoding; 3] = [Encoding::Json, Encoding::MessagePack, Encoding::Cbor];
+
+    // What a client sends in `encoding`
+    fn encode_request(encoding: Encoding, payload: &Payload) -> Message {
+        match encoding {
+            Encoding::Json => Message::Text(serde_json::to_string(payload).unwrap().into()),
+            Encoding::MessagePack => Message::Binary(rmp_serde::to_vec_named(payload).unwrap().into()),
+            Encoding::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(payload, &mut bytes).unwrap();
+                Message::Binary(bytes.into())
+            }
+        }
+    }
+
+    // What a client reads back in `encoding`
+    fn decode_response(encoding: Encoding, message: &Message) -> RoomResponse {
+        match (encoding, message) {
+            (Encoding::Json, Message::Text(text)) => serde_json::from_str(text).unwrap(),
+            (Encoding::MessagePack, Message::Binary(bytes)) => rmp_serde::from_slice(bytes).unwrap(),
+            (Encoding::Cbor, Message::Binary(bytes)) => ciborium::from_reader(bytes.as_ref()).unwrap(),
+            _ => panic!("{encoding:?} response sent in the wrong kind of frame"),
+        }
+    }
+
+    // The error code a client is sent for `message`, None when it decodes or carries no request
+    fn rejection(encoding: Encoding, message: &Message) -> Option<&'static str> {
+        match encoding.decode(message) {
+            Some(Err(err)) => Some(err.code()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn requests_round_trip_in_every_encoding() {
+        let payloads = [
+            Payload { action: Action::MakeMove, move_payload: Some(MakeMovePayload { x: 2, y: 1 }), request_id: Some("r1".to_string()) },
+            Payload { action: Action::StartGame, move_payload: None, request_id: None },
+            Payload { action: Action::Resync, move_payload: None, request_id: Some(String::new()) },
+        ];
+        for encoding in ENCODINGS {
+            for payload in &payloads {
+                let decoded = encoding.decode(&encode_request(encoding, payload)).unwrap().unwrap();
+                assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(payload).unwrap(), "{encoding:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn responses_round_trip_in_every_encoding() {
+        let ack = AckResponse { room_id: "fun".to_string(), request_id: "r1".to_string(), action: Action::MakeMove, version: 7 };
+        let responses = [
+            RoomResponse { response_type: ResponseType::Ack, response: ack.to_json_value() },
+            RoomResponse {
+                response_type: ResponseType::GameState,
+                response: json!({
+                    "room_id": "fun",
+                    "board": [["x", null, null], [null, "o", null], [null, null, null]],
+                    "current_turn": "x",
+                    "winner": null,
+                    "moves_count": 2,
+                    "version": 3,
+                }),
+            },
+            RoomResponse { response_type: ResponseType::Error, response: json!({ "code": "cell_occupied", "request_id": null }) },
+        ];
+        for encoding in ENCODINGS {
+            for response in &responses {
+                let decoded = decode_response(encoding, &encoding.encode(response));
+                let decoded: Value = serde_json::to_value(&decoded).unwrap();
+                assert_eq!(decoded, serde_json::to_value(response).unwrap(), "{encoding:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn frames_of_the_wrong_kind_are_invalid_payloads() {
+        let payload = Payload { action: Action::StartGame, move_payload: None, request_id: None };
+        let binary = encode_request(Encoding::MessagePack, &payload);
+        assert_eq!(rejection(Encoding::Json, &binary), Some("invalid_payload"));
+        let text = encode_request(Encoding::Json, &payload);
+        assert_eq!(rejection(Encoding::MessagePack, &text), Some("invalid_payload"));
+        assert_eq!(rejection(Encoding::Cbor, &text), Some("invalid_payload"));
+    }
+
+    #[test]
+    fn garbage_in_the_negotiated_encoding_is_rejected() {
+        assert_eq!(rejection(Encoding::Json, &Message::Text("{".into())), Some("invalid_json"));
+        for encoding in [Encoding::MessagePack, Encoding::Cbor] {
+            let garbage = Message::Binary(vec![0xc1, 0xff, 0x00].into());
+            assert_eq!(rejection(encoding, &garbage), Some("invalid_payload"), "{encoding:?}");
+        }
+        assert!(Encoding::Cbor.decode(&Message::Ping(Default::default())).is_none());
+    }
+}
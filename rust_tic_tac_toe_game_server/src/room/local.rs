@@ -0,0 +1,76 @@
+use axum::http::HeaderMap;
+use tracing::{info, info_span, Instrument, Span};
+use crate::auth;
+use crate::server::{ConnectionId, SharedState};
+use crate::room::{PlayerMark, RoomResponse};
+use crate::room::requests::{Action, Payload};
+use crate::room::protocol::PROTOCOL_VERSION;
+use crate::room::actor::{self, error_payload, room_state_payload, JoinOutcome, RoomCommand, RoomHandle};
+use crate::room::outbox::{self, OutboxReceiver, OutboxSender};
+
+/// A player seated in a room of this process's own server without a WebSocket, e.g. the hosting Godot instance
+/// playing in its own server. The room treats it like any other connection: it sits in `Room::connections` with
+/// an outbox like a socket's, and gets the same messages, undecoded, at the current protocol version.
+pub struct LocalConnection {
+    room_id: String,
+    room: RoomHandle,
+    connection_id: ConnectionId,
+    tx: OutboxSender,
+    rx: OutboxReceiver,
+    span: Span,
+}
+
+/// Seat a local player in `room_id`, authenticated like a WebSocket join by `token` if given. On failure the
+/// error is the message a WebSocket client would get before being closed: a `room_state` with `success: false`
+/// for a full room, or an `error` (`server_draining`, `invalid_token`, ...).
+pub async fn join_local(state: &SharedState, room_id: &str, token: Option<&str>) -> Result<LocalConnection, RoomResponse> {
+    let player = auth::authenticate(state, token, &HeaderMap::new()).map_err(|code| error_payload(room_id, code, None))?;
+    let span = info_span!("local_connection", room_id = %room_id, player_id = player.as_ref().map(|p| p.id.as_str()));
+    let config = &state.config;
+    let (tx, rx) = outbox::channel(config.outbound_queue_capacity, config.overflow_policy, state.metrics.queue.clone());
+    let (room, outcome) = actor::join(state, room_id, tx.clone(), PROTOCOL_VERSION, player)
+        .instrument(span.clone())
+        .await
+        .map_err(|code| error_payload(room_id, code, None))?;
+    match outcome {
+        JoinOutcome::Joined { connection_id, mark } => {
+            span.in_scope(|| info!(connection_id, mark = ?mark, "local player joined"));
+            Ok(LocalConnection { room_id: room_id.to_string(), room, connection_id, tx, rx, span })
+        }
+        JoinOutcome::Full { num_connections } => {
+            Err(room_state_payload(room_id, num_connections, "Room is full".to_string(), false, PlayerMark::X))
+        }
+    }
+}
+
+impl LocalConnection {
+    // Next message from the room, None once the room dropped us (kicked, room closed, server stopped).
+    pub async fn recv(&mut self) -> Option<RoomResponse> {
+        self.rx.recv().await
+    }
+
+    // Apply an action like one decoded from a socket. False when the room is gone.
+    pub async fn send(&self, payload: Payload) -> bool {
+        let connection_id = self.connection_id;
+        let request_id = payload.request_id;
+        let command = match payload.action {
+            Action::StartGame => RoomCommand::StartGame { connection_id, request_id },
+            Action::MakeMove => match payload.move_payload {
+                Some(mp) => RoomCommand::MakeMove { connection_id, x: mp.x, y: mp.y, request_id },
+                None => {
+                    let _ = self.tx.send(error_payload(&self.room_id, "missing_move_payload", request_id));
+                    return true;
+                }
+            },
+            Action::RestartGame => RoomCommand::RestartGame { connection_id, request_id },
+            Action::Resync => RoomCommand::Resync { connection_id, request_id },
+        };
+        self.room.send(command).await
+    }
+
+    // Give up the seat; the other player sees a leave, as for a closed socket.
+    pub async fn leave(self) {
+        self.room.send(RoomCommand::Leave { connection_id: self.connection_id }).await;
+        self.span.in_scope(|| info!("local player left"));
+    }
+}
@@ -1,7 +1,35 @@
-mod room;
+// The protocol types are shared with the client; the rest is the server side of a room.
+#[cfg(feature = "server")]
+mod socket;
 mod responses;
 mod requests;
+mod protocol;
+#[cfg(feature = "server")]
+mod codec;
+#[cfg(feature = "server")]
+mod actor;
+#[cfg(feature = "server")]
+mod outbox;
+#[cfg(feature = "server")]
+mod admin;
+#[cfg(all(feature = "server", feature = "client"))]
+mod local;
 
-pub use room::join_room;
+#[cfg(feature = "server")]
+pub use socket::join_room;
+#[cfg(feature = "server")]
+pub use protocol::protocol_schema;
+pub use protocol::PROTOCOL_VERSION;
+#[cfg(feature = "client")]
+pub use requests::{Action, MakeMovePayload, Payload};
+#[cfg(feature = "server")]
+pub use actor::{RoomCommand, RoomHandle};
+#[cfg(feature = "server")]
+pub use admin::admin_routes;
+#[cfg(all(feature = "server", feature = "client"))]
+pub use local::{join_local, LocalConnection};
+#[cfg(feature = "server")]
+pub use outbox::{OutboxSender, OverflowPolicy, QueueMetrics};
 pub use responses::*;
-pub use responses::PlayerMark;
+#[cfg(feature = "server")]
+pub use crate::engine::PlayerMark;
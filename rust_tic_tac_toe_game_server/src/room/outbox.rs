@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use prometheus::IntCounter;
+use tokio::sync::Notify;
+use tracing::warn;
+use crate::room::{ResponseType, RoomResponse};
+
+/// What to do when a connection's outbound queue is full because the client reads slower than the room writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Discard the oldest queued message to make room
+    DropOldest,
+    // Discard queued game/latency updates made redundant by the new one; falls back to dropping the oldest
+    CoalesceState,
+    // Close the connection
+    Disconnect,
+}
+
+/// Server-wide counters for messages lost to full outbound queues, registered by `metrics::Metrics`.
+pub struct QueueMetrics {
+    pub dropped: IntCounter,
+    pub coalesced: IntCounter,
+    pub disconnected: IntCounter,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendError {
+    // The connection's socket task is gone
+    Closed,
+    // The queue was full and the policy is `Disconnect`
+    Overflow,
+}
+
+struct Shared {
+    queue: Mutex<VecDeque<RoomResponse>>,
+    notify: Notify,
+    closed: AtomicBool,
+    // Set by `finish`: no new messages, end once the queue is drained
+    finishing: AtomicBool,
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: Arc<QueueMetrics>,
+}
+
+/// Producer side, held by the room actor (and the connection's own handler for direct errors).
+#[derive(Clone)]
+pub struct OutboxSender {
+    shared: Arc<Shared>,
+}
+
+/// Consumer side, drained by the connection's socket send task.
+pub struct OutboxReceiver {
+    shared: Arc<Shared>,
+}
+
+pub fn channel(capacity: usize, policy: OverflowPolicy, metrics: Arc<QueueMetrics>) -> (OutboxSender, OutboxReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::with_capacity(capacity)),
+        notify: Notify::new(),
+        closed: AtomicBool::new(false),
+        finishing: AtomicBool::new(false),
+        capacity: capacity.max(1),
+        policy,
+        metrics,
+    });
+    (OutboxSender { shared: shared.clone() }, OutboxReceiver { shared })
+}
+
+// Whether a queued message is made redundant by a newer one. A full game state replaces any older game update,
+// and a `move_made` replaces older moves (a skipped move shows up client-side as a version gap and triggers a
+// `resync`) but never a full game state, which may carry what no move does, e.g. a restart's empty board.
+// A latency report replaces older latency reports.
+fn supersedes(newer: ResponseType, older: ResponseType) -> bool {
+    match newer {
+        ResponseType::GameState => matches!(older, ResponseType::GameState | ResponseType::MoveMade),
+        ResponseType::MoveMade => older == ResponseType::MoveMade,
+        ResponseType::Latency => older == ResponseType::Latency,
+        _ => false,
+    }
+}
+
+impl OutboxSender {
+    // Never blocks: a full queue is resolved immediately according to the overflow policy.
+    pub fn send(&self, msg: RoomResponse) -> Result<(), SendError> {
+        let shared = &self.shared;
+        if shared.closed.load(Ordering::Acquire) || shared.finishing.load(Ordering::Acquire) {
+            return Err(SendError::Closed);
+        }
+        {
+            let mut queue = shared.queue.lock().unwrap();
+            if queue.len() >= shared.capacity {
+                match shared.policy {
+                    OverflowPolicy::Disconnect => {
+                        drop(queue);
+                        shared.metrics.disconnected.inc();
+                        warn!(capacity = shared.capacity, "outbound queue overflowed, disconnecting slow client");
+                        self.close();
+                        return Err(SendError::Overflow);
+                    }
+                    OverflowPolicy::DropOldest => {
+                        queue.pop_front();
+                        shared.metrics.dropped.inc();
+                    }
+                    OverflowPolicy::CoalesceState => {
+                        let before = queue.len();
+                        queue.retain(|old| !supersedes(msg.response_type, old.response_type));
+                        let removed = (before - queue.len()) as u64;
+                        if removed > 0 {
+                            shared.metrics.coalesced.inc_by(removed);
+                        } else {
+                            queue.pop_front();
+                            shared.metrics.dropped.inc();
+                        }
+                    }
+                }
+            }
+            queue.push_back(msg);
+        }
+        shared.notify.notify_one();
+        Ok(())
+    }
+
+    // Stop the connection: the receiver returns None without flushing what's still queued.
+    pub fn close(&self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+    }
+
+    // Stop accepting messages and end the connection once everything already queued has been written.
+    pub fn finish(&self) {
+        self.shared.finishing.store(true, Ordering::Release);
+        self.shared.notify.notify_one();
+    }
+}
+
+impl OutboxReceiver {
+    pub async fn recv(&mut self) -> Option<RoomResponse> {
+        loop {
+            if self.shared.closed.load(Ordering::Acquire) {
+                return None;
+            }
+            if let Some(msg) = self.shared.queue.lock().unwrap().pop_front() {
+                return Some(msg);
+            }
+            if self.shared.finishing.load(Ordering::Acquire) {
+                return None;
+            }
+            // `notify_one` stores a permit when nobody is waiting yet, so a send between the check above and
+            // this await is not lost
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for OutboxReceiver {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metrics() -> Arc<QueueMetrics> {
+        let counter = |name: &str| IntCounter::new(name, name).unwrap();
+        Arc::new(QueueMetrics { dropped: counter("dropped"), coalesced: counter("coalesced"), disconnected: counter("disconnected") })
+    }
+
+    fn msg(response_type: ResponseType, n: u64) -> RoomResponse {
+        RoomResponse { response_type, response: json!({ "n": n }) }
+    }
+
+    // (type, n) of everything queued, oldest first
+    fn queued(rx: &OutboxReceiver) -> Vec<(ResponseType, u64)> {
+        rx.shared.queue.lock().unwrap().iter().map(|m| (m.response_type, m.response["n"].as_u64().unwrap())).collect()
+    }
+
+    #[test]
+    fn drop_oldest_makes_room_for_the_new_message() {
+        let (tx, rx) = channel(2, OverflowPolicy::DropOldest, metrics());
+        for n in 1..=3 {
+            tx.send(msg(ResponseType::Notice, n)).unwrap();
+        }
+        assert_eq!(queued(&rx), [(ResponseType::Notice, 2), (ResponseType::Notice, 3)]);
+        assert_eq!(rx.shared.metrics.dropped.get(), 1);
+    }
+
+    #[test]
+    fn disconnect_closes_the_connection_on_overflow() {
+        let (tx, rx) = channel(1, OverflowPolicy::Disconnect, metrics());
+        tx.send(msg(ResponseType::Notice, 1)).unwrap();
+        assert_eq!(tx.send(msg(ResponseType::Notice, 2)), Err(SendError::Overflow));
+        assert_eq!(tx.send(msg(ResponseType::Notice, 3)), Err(SendError::Closed));
+        assert_eq!(rx.shared.metrics.disconnected.get(), 1);
+    }
+
+    #[test]
+    fn coalesce_replaces_older_moves_with_a_newer_one() {
+        let (tx, rx) = channel(3, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::RoomState, 1)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 2)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 3)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 4)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::RoomState, 1), (ResponseType::MoveMade, 4)]);
+        assert_eq!(rx.shared.metrics.coalesced.get(), 2);
+    }
+
+    #[test]
+    fn coalesce_replaces_any_game_update_with_a_full_state() {
+        let (tx, rx) = channel(3, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::GameState, 1)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 2)).unwrap();
+        tx.send(msg(ResponseType::Ack, 3)).unwrap();
+        tx.send(msg(ResponseType::GameState, 4)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::Ack, 3), (ResponseType::GameState, 4)]);
+    }
+
+    #[test]
+    fn coalesce_never_drops_a_full_state_for_a_move() {
+        let (tx, rx) = channel(3, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::GameState, 1)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 2)).unwrap();
+        tx.send(msg(ResponseType::Notice, 3)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 4)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::GameState, 1), (ResponseType::Notice, 3), (ResponseType::MoveMade, 4)]);
+        // No older move to replace: the oldest message is dropped, as with `DropOldest`
+        let (tx, rx) = channel(2, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::GameState, 1)).unwrap();
+        tx.send(msg(ResponseType::Notice, 2)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 3)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::Notice, 2), (ResponseType::MoveMade, 3)]);
+        assert_eq!(rx.shared.metrics.dropped.get(), 1);
+    }
+
+    #[test]
+    fn coalesce_replaces_latency_reports_only_with_latency_reports() {
+        let (tx, rx) = channel(2, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::Latency, 1)).unwrap();
+        tx.send(msg(ResponseType::MoveMade, 2)).unwrap();
+        tx.send(msg(ResponseType::Latency, 3)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::MoveMade, 2), (ResponseType::Latency, 3)]);
+    }
+
+    #[test]
+    fn coalesce_falls_back_to_dropping_the_oldest() {
+        let (tx, rx) = channel(2, OverflowPolicy::CoalesceState, metrics());
+        tx.send(msg(ResponseType::Notice, 1)).unwrap();
+        tx.send(msg(ResponseType::Ack, 2)).unwrap();
+        tx.send(msg(ResponseType::Error, 3)).unwrap();
+        assert_eq!(queued(&rx), [(ResponseType::Ack, 2), (ResponseType::Error, 3)]);
+        assert_eq!(rx.shared.metrics.dropped.get(), 1);
+        assert_eq!(rx.shared.metrics.coalesced.get(), 0);
+    }
+}
@@ -0,0 +1,63 @@
+#[cfg(feature = "server")]
+use axum::Json;
+#[cfg(feature = "server")]
+use schemars::schema_for;
+#[cfg(feature = "server")]
+use serde_json::{json, Value};
+#[cfg(feature = "server")]
+use crate::room::requests::Payload;
+#[cfg(feature = "server")]
+use crate::room::{AckResponse, ErrorResponse, GameStateResponse, LatencyResponse, MoveMadeResponse, NoticeResponse, RoomResponse, RoomStateResponse};
+
+/// Protocol version spoken by this server. Bump it whenever the wire format changes.
+pub const PROTOCOL_VERSION: u32 = 4;
+
+/// Oldest client protocol version the server still accepts.
+#[cfg(feature = "server")]
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Clients that don't send a version are assumed to speak the original (v1) protocol.
+#[cfg(feature = "server")]
+pub const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
+/// First version receiving `move_made` deltas; older clients get a full `game_state` after every move.
+#[cfg(feature = "server")]
+pub const DELTA_UPDATES_VERSION: u32 = 2;
+
+/// First version receiving `latency` reports.
+#[cfg(feature = "server")]
+pub const LATENCY_REPORTS_VERSION: u32 = 3;
+
+/// First version receiving server `notice` messages.
+#[cfg(feature = "server")]
+pub const NOTICES_VERSION: u32 = 4;
+
+#[cfg(feature = "server")]
+pub fn is_supported(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+// Bundle the schemas of every message type so clients can validate or generate code from one document.
+#[cfg(feature = "server")]
+pub fn json_schema() -> Value {
+    json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "min_protocol_version": MIN_PROTOCOL_VERSION,
+        "request": schema_for!(Payload),
+        "envelope": schema_for!(RoomResponse),
+        "responses": {
+            "room_state": schema_for!(RoomStateResponse),
+            "game_state": schema_for!(GameStateResponse),
+            "move_made": schema_for!(MoveMadeResponse),
+            "ack": schema_for!(AckResponse),
+            "latency": schema_for!(LatencyResponse),
+            "notice": schema_for!(NoticeResponse),
+            "error": schema_for!(ErrorResponse),
+        },
+    })
+}
+
+#[cfg(feature = "server")]
+pub async fn protocol_schema() -> Json<Value> {
+    Json(json_schema())
+}
@@ -1,21 +1,38 @@
-use serde::Deserialize;
+#[cfg(feature = "server")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum Action {
     MakeMove,
     StartGame,
     RestartGame,
+    Resync,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct Payload {
     pub action: Action,
     pub move_payload: Option<MakeMovePayload>,
+    // Optional client-chosen id echoed back in the matching `ack` or `error`
+    pub request_id: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct MakeMovePayload {
     pub x: u8,
     pub y: u8,
 }
+
+// Query string accepted on `/join/{room_id}`, e.g. `/join/my-room?version=1`.
+#[cfg(feature = "server")]
+#[derive(Deserialize)]
+pub struct JoinParams {
+    pub version: Option<u32>,
+    // Session token from `/auth/guest`; browsers can't set headers on a WebSocket handshake
+    pub token: Option<String>,
+}
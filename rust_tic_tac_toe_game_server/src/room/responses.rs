@@ -1,37 +1,30 @@
-use serde::Serialize;
+#[cfg(feature = "server")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+#[cfg(feature = "server")]
+use crate::room::requests::Action;
 
-#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
-pub enum PlayerMark {
-    X,
-    O,
-}
-
-impl PlayerMark {
-    pub fn to_string(&self) -> String {
-        match self {
-            PlayerMark::X => "x".to_string(),
-            PlayerMark::O => "o".to_string(),
-        }
-    }
-}
-
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct RoomStateResponse {
     pub room_id: String,
     pub num_connections: usize,
     pub message: String,
     pub success: bool,
     pub my_mark: String,
+    pub protocol_version: u32,
 }
 
+#[cfg(feature = "server")]
 impl RoomStateResponse {
     pub fn to_json_value(&self) -> Value {
         serde_json::to_value(self).unwrap()
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct GameStateResponse {
     pub room_id: String,
     pub board: Vec<Vec<Option<String>>>,
@@ -39,30 +32,108 @@ pub struct GameStateResponse {
     pub winner: Option<String>,
     pub started: bool,
     pub moves_count: u8,
+    pub version: u64,
 }
 
+#[cfg(feature = "server")]
 impl GameStateResponse {
     pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() }
 }
 
-#[derive(Serialize)]
+// Delta sent after a successful move instead of the full board.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
+pub struct MoveMadeResponse {
+    pub room_id: String,
+    pub x: u8,
+    pub y: u8,
+    pub mark: String,
+    pub version: u64,
+    pub current_turn: Option<String>,
+    pub winner: Option<String>,
+    pub moves_count: u8,
+}
+
+#[cfg(feature = "server")]
+impl MoveMadeResponse {
+    pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
+pub struct PlayerLatency {
+    pub my_mark: String,
+    pub latency_ms: Option<u64>,
+}
+
+// Per-player round-trip times, broadcast whenever a player answers a heartbeat ping.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
+pub struct LatencyResponse {
+    pub room_id: String,
+    pub players: Vec<PlayerLatency>,
+}
+
+#[cfg(feature = "server")]
+impl LatencyResponse {
+    pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() }
+}
+
+// Sent only to the requesting connection when an action carrying a `request_id` succeeds.
+#[cfg(feature = "server")]
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct AckResponse {
+    pub room_id: String,
+    pub request_id: String,
+    pub action: Action,
+    pub version: u64,
+}
+
+#[cfg(feature = "server")]
+impl AckResponse {
+    pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() }
+}
+
+// Operator message pushed by the admin API, e.g. before a room is closed or the server restarts.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
+pub struct NoticeResponse {
+    pub room_id: String,
+    pub message: String,
+}
+
+#[cfg(feature = "server")]
+impl NoticeResponse {
+    pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() }
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct ErrorResponse {
     pub room_id: String,
     pub code: String,
     pub message: String,
+    pub request_id: Option<String>,
 }
 
+#[cfg(feature = "server")]
 impl ErrorResponse { pub fn to_json_value(&self) -> Value { serde_json::to_value(self).unwrap() } }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum ResponseType {
     RoomState,
     GameState,
+    MoveMade,
+    Ack,
+    Latency,
+    Notice,
     Error,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "server", derive(JsonSchema))]
 pub struct RoomResponse {
     pub response_type: ResponseType,
     pub response: Value,
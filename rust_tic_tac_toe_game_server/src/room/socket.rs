@@ -0,0 +1,267 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade};
+use axum::extract::ws::{Message, WebSocket};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use futures_util::{SinkExt, StreamExt};
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
+use crate::auth::{self, Player};
+use crate::server::SharedState;
+use crate::room::{PlayerMark, RoomResponse, ResponseType, ErrorResponse};
+use crate::room::requests::{Action, JoinParams};
+use crate::room::protocol::{self, PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, LEGACY_PROTOCOL_VERSION};
+use crate::room::codec::{self, Encoding};
+use crate::room::actor::{self, error_payload, room_state_payload, JoinOutcome, RoomCommand};
+use crate::room::outbox;
+use crate::rate_limit::TokenBucket;
+
+// Messages up to this many times `max_message_size` are read and answered with `message_too_large`; anything
+// bigger is cut off by the WebSocket layer before being buffered.
+const HARD_MESSAGE_SIZE_FACTOR: usize = 4;
+
+// How long a connection we're closing on purpose gets to flush its final error.
+const FINAL_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub async fn join_room(
+    Path(room_id): Path<String>,
+    Query(params): Query<JoinParams>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+    State(state): State<SharedState>,
+) -> Response {
+    let ip = addr.ip();
+    // Refuse join floods before the upgrade, so they never reach a room
+    if !state.ip_join_limiter.try_take(ip) {
+        state.metrics.errors.with_label_values(&["rate_limited"]).inc();
+        info!(%ip, room_id, "join rejected: rate limited");
+        return (StatusCode::TOO_MANY_REQUESTS, "rate_limited").into_response();
+    }
+    // Bad or missing (when required) tokens are refused with a 401 before upgrading
+    let player = match auth::authenticate(&state, params.token.as_deref(), &headers) {
+        Ok(player) => player,
+        Err(code) => {
+            info!(%ip, room_id, code, "join rejected: not authenticated");
+            return (StatusCode::UNAUTHORIZED, code).into_response();
+        }
+    };
+
+    let client_version = params.version.unwrap_or(LEGACY_PROTOCOL_VERSION);
+    let hard_limit = state.config.max_message_size * HARD_MESSAGE_SIZE_FACTOR;
+    let ws = ws.protocols(codec::SUBPROTOCOLS).max_message_size(hard_limit).max_frame_size(hard_limit);
+    let encoding = Encoding::from_subprotocol(ws.selected_protocol());
+    // connection_id and mark are recorded once the room has seated us
+    let span = info_span!(
+        "connection",
+        room_id = %room_id,
+        %ip,
+        player_id = player.as_ref().map(|p| p.id.as_str()),
+        connection_id = field::Empty,
+        mark = field::Empty,
+    );
+    ws.on_upgrade(move |socket| handle_join_room(room_id, ip, client_version, encoding, player, socket, state).instrument(span))
+}
+
+// Liveness bookkeeping shared between a connection's send (ping) and receive (pong) tasks.
+struct Heartbeat {
+    last_seen: Instant,
+    ping_sent_at: Option<Instant>,
+}
+
+// Thin adapter between one WebSocket and its room's actor: decodes frames into `RoomCommand`s and
+// forwards whatever the actor queues for this connection back out on the socket.
+async fn handle_join_room(
+    room_id: String,
+    ip: IpAddr,
+    client_version: u32,
+    encoding: Encoding,
+    player: Option<Player>,
+    socket: WebSocket,
+    state: SharedState,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    state.metrics.ws_connects.inc();
+
+    // Reject clients speaking a protocol version we can't understand before touching any room state.
+    if !protocol::is_supported(client_version) {
+        let err = ErrorResponse {
+            room_id: room_id.clone(),
+            code: "unsupported_protocol_version".to_string(),
+            message: format!(
+                "client protocol version {} is not supported (server supports {}..={})",
+                client_version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+            request_id: None,
+        };
+        let payload = RoomResponse { response_type: ResponseType::Error, response: err.to_json_value() };
+        state.metrics.errors.with_label_values(&["unsupported_protocol_version"]).inc();
+        warn!(client_version, "rejected: unsupported protocol version");
+        let _ = sender.send(encoding.encode(&payload)).await;
+        let _ = sender.send(Message::Close(None)).await;
+        state.metrics.ws_disconnects.inc();
+        return;
+    }
+
+    let config = state.config.clone();
+    let (tx, mut rx) = outbox::channel(config.outbound_queue_capacity, config.overflow_policy, state.metrics.queue.clone());
+
+    // The room's actor checks capacity and registers us in one step, so a room never exceeds 2 connections.
+    let (room, outcome) = match actor::join(&state, &room_id, tx.clone(), client_version, player).await {
+        Ok(joined) => joined,
+        Err(code) => {
+            // Draining: this would have created a new room
+            state.metrics.errors.with_label_values(&[code]).inc();
+            info!(code, "rejected: server is draining");
+            let _ = sender.send(encoding.encode(&error_payload(&room_id, code, None))).await;
+            let _ = sender.send(Message::Close(None)).await;
+            state.metrics.ws_disconnects.inc();
+            return;
+        }
+    };
+    let connection_id = match outcome {
+        JoinOutcome::Joined { connection_id, mark } => {
+            Span::current().record("connection_id", connection_id).record("mark", field::debug(mark));
+            connection_id
+        }
+        JoinOutcome::Full { num_connections } => {
+            // Room already full: notify the joining socket and close it.
+            let payload = room_state_payload(&room_id, num_connections, "Room is full".to_string(), false, PlayerMark::X); // dummy mark
+            let _ = sender.send(encoding.encode(&payload)).await;
+            let _ = sender.send(Message::Close(None)).await;
+            state.metrics.ws_disconnects.inc();
+            return;
+        }
+    };
+
+    let room_for_recv = room.clone();
+    let room_id_for_recv = room_id.clone();
+    let state_for_recv = state.clone();
+    let heartbeat = Arc::new(Mutex::new(Heartbeat { last_seen: Instant::now(), ping_sent_at: None }));
+    let heartbeat_for_recv = heartbeat.clone();
+
+    let mut send_task = tokio::spawn(async move {
+        let mut ping_interval = tokio::time::interval(config.ping_interval);
+        // The first tick completes immediately; skip it so the first ping goes out after one interval
+        ping_interval.tick().await;
+        loop {
+            tokio::select! {
+                msg = rx.recv() => {
+                    let Some(msg) = msg else {
+                        // The room closed our outbox (kicked, room closed, or queue overflow)
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    };
+                    if sender.send(encoding.encode(&msg)).await.is_err() { break; }
+                }
+                _ = ping_interval.tick() => {
+                    let idle_for = heartbeat.lock().unwrap().last_seen.elapsed();
+                    // Half-open or unresponsive socket: give up so the seat is freed instead of waiting for a send to fail
+                    if idle_for > config.idle_timeout {
+                        info!(?idle_for, "idle timeout, dropping connection");
+                        break;
+                    }
+                    heartbeat.lock().unwrap().ping_sent_at = Some(Instant::now());
+                    if sender.send(Message::Ping(Bytes::new())).await.is_err() { break; }
+                }
+            }
+        }
+    }.in_current_span());
+
+    // Returns true when it ended the connection itself after queueing a final error that should still be flushed.
+    let mut recv_task = tokio::spawn(async move {
+        let reject = |code: &str, request_id: Option<String>| {
+            state_for_recv.metrics.errors.with_label_values(&[code]).inc();
+            let _ = tx.send(error_payload(&room_id_for_recv, code, request_id));
+        };
+        let mut action_bucket = TokenBucket::new(config.connection_action_limit);
+        let mut violations = 0;
+        while let Some(msg) = receiver.next().await {
+            let Ok(msg) = msg else { break; };
+            // Any frame from the client proves the connection is alive, even one dropped by the rate limit below
+            let ping_sent_at = {
+                let mut hb = heartbeat_for_recv.lock().unwrap();
+                hb.last_seen = Instant::now();
+                if matches!(msg, Message::Pong(_)) { hb.ping_sent_at.take() } else { None }
+            };
+            if let Message::Text(_) | Message::Binary(_) = &msg {
+                let size = match &msg {
+                    Message::Text(text) => text.len(),
+                    Message::Binary(data) => data.len(),
+                    _ => 0,
+                };
+                if size > config.max_message_size {
+                    info!(size, "disconnecting: message too large");
+                    reject("message_too_large", None);
+                    tx.finish();
+                    return true;
+                }
+                // Checked before decoding, so floods of garbage cost as little as floods of valid actions
+                if !(action_bucket.try_take() && state_for_recv.ip_action_limiter.try_take(ip)) {
+                    violations += 1;
+                    debug!(violations, "action rate limited");
+                    reject("rate_limited", None);
+                    if violations >= config.max_rate_limit_violations {
+                        info!(violations, "disconnecting: rate limit exceeded");
+                        tx.finish();
+                        return true;
+                    }
+                    continue;
+                }
+            }
+            let command = match msg {
+                Message::Close(_) => {
+                    debug!("client sent close");
+                    break;
+                }
+                Message::Pong(_) => match ping_sent_at {
+                    Some(sent_at) => RoomCommand::Latency { connection_id, latency_ms: sent_at.elapsed().as_millis() as u64 },
+                    None => continue,
+                },
+                msg => match encoding.decode(&msg) {
+                    // Parse JSON text or negotiated binary payload
+                    Some(Ok(payload)) => {
+                        let request_id = payload.request_id;
+                        match payload.action {
+                            Action::StartGame => RoomCommand::StartGame { connection_id, request_id },
+                            Action::MakeMove => match payload.move_payload {
+                                Some(mp) => RoomCommand::MakeMove { connection_id, x: mp.x, y: mp.y, request_id },
+                                None => {
+                                    reject("missing_move_payload", request_id);
+                                    continue;
+                                }
+                            },
+                            Action::RestartGame => RoomCommand::RestartGame { connection_id, request_id },
+                            Action::Resync => RoomCommand::Resync { connection_id, request_id },
+                        }
+                    }
+                    Some(Err(e)) => {
+                        debug!(code = e.code(), "undecodable frame");
+                        reject(e.code(), None);
+                        continue;
+                    }
+                    None => continue,
+                },
+            };
+            if !room_for_recv.send(command).await { break; }
+        }
+        false
+    }.in_current_span());
+
+    tokio::select! {
+        _ = &mut send_task => recv_task.abort(),
+        flush = &mut recv_task => {
+            if matches!(flush, Ok(true)) {
+                let _ = tokio::time::timeout(FINAL_FLUSH_TIMEOUT, &mut send_task).await;
+            }
+            send_task.abort();
+        }
+    }
+
+    // Let the room drop this connection and announce the leave to everyone else
+    room.send(RoomCommand::Leave { connection_id }).await;
+    state.metrics.ws_disconnects.inc();
+    info!("connection closed");
+}
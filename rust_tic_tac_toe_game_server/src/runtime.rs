@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use futures_util::future::join_all;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+// How long services get to wind down (close rooms, say goodbye) before the runtime is torn down regardless
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The Tokio runtime the extension's server, discovery and local seats run on. Started when the extension
+/// loads and shut down when it unloads, so a hot reload in the editor leaves no threads or bound sockets behind.
+struct ExtensionRuntime {
+    runtime: Runtime,
+    stopping: watch::Sender<bool>,
+    // Tasks `shutdown` waits for
+    services: Vec<JoinHandle<()>>,
+}
+
+static RUNTIME: Mutex<Option<ExtensionRuntime>> = Mutex::new(None);
+
+fn started(slot: &mut Option<ExtensionRuntime>) -> &mut ExtensionRuntime {
+    slot.get_or_insert_with(|| {
+        let runtime = Runtime::new().expect("Failed to create Tokio runtime");
+        info!("tokio runtime started");
+        ExtensionRuntime { runtime, stopping: watch::channel(false).0, services: Vec::new() }
+    })
+}
+
+/// Resolves once the runtime is shutting down (or already gone).
+pub struct Stopping(watch::Receiver<bool>);
+
+impl Stopping {
+    pub async fn wait(mut self) {
+        let _ = self.0.wait_for(|&stopping| stopping).await;
+    }
+}
+
+// Idempotent; anything spawned before (or after a shutdown) starts it on demand.
+pub fn start() {
+    started(&mut RUNTIME.lock().unwrap());
+}
+
+pub fn handle() -> Handle {
+    started(&mut RUNTIME.lock().unwrap()).runtime.handle().clone()
+}
+
+// A task that is simply dropped on shutdown.
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    handle().spawn(future);
+}
+
+// Handed to a service so it can wind down on its own when the runtime shuts down.
+pub fn stopping() -> Stopping {
+    Stopping(started(&mut RUNTIME.lock().unwrap()).stopping.subscribe())
+}
+
+// A task `shutdown` waits for (up to a timeout), e.g. a server that should free its port. It must end once
+// `stopping()` resolves.
+pub fn spawn_service<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let mut slot = RUNTIME.lock().unwrap();
+    let runtime = started(&mut slot);
+    runtime.services.retain(|service| !service.is_finished());
+    let service = runtime.runtime.spawn(future);
+    runtime.services.push(service);
+}
+
+/// Ask every service to stop, give them `SHUTDOWN_TIMEOUT` to finish, then drop all remaining tasks and join the
+/// worker threads. Must not be called from within the runtime.
+pub fn shutdown() {
+    // Taken out first: services winding down must not find the lock held
+    let Some(ExtensionRuntime { runtime, stopping, services }) = RUNTIME.lock().unwrap().take() else { return; };
+    info!(services = services.len(), "tokio runtime shutting down");
+    let _ = stopping.send(true);
+    let stopped = runtime.block_on(async { tokio::time::timeout(SHUTDOWN_TIMEOUT, join_all(services)).await });
+    if stopped.is_err() {
+        warn!("services did not stop in time");
+    }
+    runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+}
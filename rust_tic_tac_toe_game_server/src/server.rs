@@ -1,33 +1,74 @@
-use axum::routing::get;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::routing::{get, post};
 use axum::Router;
+use dashmap::DashMap;
 use std::collections::HashMap;
+use std::future::Future;
+use std::io;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::info;
+use crate::auth::{self, Player, TokenSigner};
+use crate::cors;
+use crate::engine::Game;
+use crate::metrics::{self, Metrics};
+#[cfg(feature = "tls")]
+use crate::tls;
+use crate::rate_limit::IpRateLimiter;
 use crate::room;
+use crate::room::{OverflowPolicy, RoomCommand, RoomHandle};
+
+pub use crate::rate_limit::RateLimit;
+pub use crate::room::PROTOCOL_VERSION;
+#[cfg(feature = "tls")]
+pub use crate::tls::{generate_self_signed, TlsConfig};
 
 // A simple identifier for each WebSocket connection.
 pub(crate) type ConnectionId = u64;
 
+pub struct Connection {
+    // Bounded queue feeding this connection's outbound socket task
+    pub tx: crate::room::OutboxSender,
+    pub mark: crate::room::PlayerMark,
+    // Protocol version negotiated at connect, decides which message kinds this client understands
+    pub protocol_version: u32,
+    // Round-trip time of the last answered WebSocket ping, if any
+    pub latency_ms: Option<u64>,
+    // Verified identity from the session token, None for anonymous players
+    pub player: Option<Player>,
+}
+
 pub struct Room {
-    // Map of connection id -> (sender channel for that connection, assigned PlayerMark)
-    pub connections: HashMap<ConnectionId, (mpsc::UnboundedSender<String>, crate::room::PlayerMark)>,
-    pub board: [[Option<crate::room::PlayerMark>; 3]; 3],
+    // Map of connection id -> connection (sender channel, assigned PlayerMark, protocol version)
+    pub connections: HashMap<ConnectionId, Connection>,
+    pub game: Game,
     pub started: bool,
-    pub current_turn: crate::room::PlayerMark,
-    pub winner: Option<crate::room::PlayerMark>,
-    pub moves_count: u8,
+    // Monotonically increasing game state version, bumped on every state transition
+    pub version: u64,
+    // Mark each verified player last held here, so a reconnect gets its seat back
+    pub seats: HashMap<String, crate::room::PlayerMark>,
+}
+
+impl Default for Room {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Room {
     pub fn new() -> Self {
         Self {
             connections: HashMap::new(),
-            board: [[None; 3]; 3],
+            game: Game::new(),
             started: false,
-            current_turn: crate::room::PlayerMark::X,
-            winner: None,
-            moves_count: 0,
+            version: 0,
+            seats: HashMap::new(),
         }
     }
 
@@ -35,94 +76,300 @@ impl Room {
         if self.started {
             return Err("game_already_started");
         }
-        if self.connections.len() < 2 {
+        if self.connections.len() < ROOM_CAPACITY {
+            return Err("not_enough_players");
+        }
+        self.started = true;
+        self.game = Game::new();
+        self.version += 1;
+        Ok(())
+    }
+
+    pub fn restart_game(&mut self) -> Result<(), &'static str> {
+        // Restarting needs a full room, like starting
+        if self.connections.len() < ROOM_CAPACITY {
             return Err("not_enough_players");
         }
+        self.game = Game::new();
         self.started = true;
-        self.current_turn = crate::room::PlayerMark::X;
-        self.winner = None;
-        self.moves_count = 0;
-        self.board = [[None; 3]; 3];
+        self.version += 1;
         Ok(())
     }
 
+    pub fn is_finished(&self) -> bool {
+        self.game.is_finished()
+    }
+
+    // The mark not held by anyone yet, so a player rejoining after a leave never duplicates the remaining one.
+    pub fn free_mark(&self) -> crate::room::PlayerMark {
+        let x_taken = self.connections.values().any(|c| c.mark == crate::room::PlayerMark::X);
+        if x_taken { crate::room::PlayerMark::O } else { crate::room::PlayerMark::X }
+    }
+
+    // A verified player gets the mark they held before if it's still free, anyone else the free mark.
+    pub fn mark_for(&self, player: Option<&Player>) -> crate::room::PlayerMark {
+        let previous = player.and_then(|p| self.seats.get(&p.id).copied());
+        match previous {
+            Some(mark) if !self.connections.values().any(|c| c.mark == mark) => mark,
+            _ => self.free_mark(),
+        }
+    }
+
     pub fn make_move(&mut self, player: crate::room::PlayerMark, x: u8, y: u8) -> Result<(), &'static str> {
         if !self.started {
             return Err("game_not_started");
         }
-        if self.winner.is_some() {
-            return Err("game_already_finished");
-        }
-        if player != self.current_turn {
-            return Err("not_your_turn");
-        }
-        if x > 2 || y > 2 {
-            return Err("out_of_bounds");
-        }
-        let xi = x as usize;
-        let yi = y as usize;
-        if self.board[yi][xi].is_some() {
-            return Err("cell_occupied");
-        }
-        self.board[yi][xi] = Some(player);
-        self.moves_count += 1;
-        // Check winner or draw
-        if let Some(winner) = Self::check_winner(&self.board) {
-            self.winner = Some(winner);
-        } else if self.moves_count >= 9 {
-            // draw -> winner stays None but game considered finished
-        } else {
-            self.current_turn = match self.current_turn {
-                crate::room::PlayerMark::X => crate::room::PlayerMark::O,
-                crate::room::PlayerMark::O => crate::room::PlayerMark::X,
-            };
-        }
+        self.game.make_move(player, x, y)?;
+        self.version += 1;
         Ok(())
     }
+}
 
-    fn check_winner(board: &[[Option<crate::room::PlayerMark>; 3]; 3]) -> Option<crate::room::PlayerMark> {
-        let lines = [
-            // Rows
-            [(0,0),(1,0),(2,0)],
-            [(0,1),(1,1),(2,1)],
-            [(0,2),(1,2),(2,2)],
-            // Cols
-            [(0,0),(0,1),(0,2)],
-            [(1,0),(1,1),(1,2)],
-            [(2,0),(2,1),(2,2)],
-            // Diagonals
-            [(0,0),(1,1),(2,2)],
-            [(2,0),(1,1),(0,2)],
-        ];
-        for line in lines.iter() {
-            let [a,b,c] = line;
-            if let (Some(m1), Some(m2), Some(m3)) = (board[a.1][a.0], board[b.1][b.0], board[c.1][c.0]) {
-                if m1 == m2 && m2 == m3 { return Some(m1); }
-            }
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub port: u16,
+    // How often the server pings each connection
+    pub ping_interval: Duration,
+    // Connections that haven't sent anything (including pongs) for this long are dropped
+    pub idle_timeout: Duration,
+    // Maximum number of messages waiting to be written to a single connection
+    pub outbound_queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+    // Bearer token for the `/admin` API; the API is disabled when unset
+    pub admin_token: Option<String>,
+    // Largest text/binary message accepted from a client, in bytes
+    pub max_message_size: usize,
+    // Actions (any text/binary frame) per connection, and per client IP across all its connections
+    pub connection_action_limit: RateLimit,
+    pub ip_action_limit: RateLimit,
+    // WebSocket join attempts per client IP
+    pub ip_join_limit: RateLimit,
+    // Rate-limited frames a connection may send before it is disconnected
+    pub max_rate_limit_violations: u32,
+    // HMAC key for session tokens; a random per-process key when unset (tokens die with the process)
+    pub auth_secret: Option<String>,
+    pub token_ttl: Duration,
+    // Reject joins without a valid session token
+    pub require_auth: bool,
+    // Browser origins (e.g. `https://game.example.com`) allowed to call the HTTP endpoints and open WebSockets;
+    // None allows any origin. Native clients, which send no `Origin`, are never affected
+    pub allowed_origins: Option<Vec<String>>,
+    // Serve `wss://` instead of `ws://` when set
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 3000,
+            ping_interval: Duration::from_secs(10),
+            idle_timeout: Duration::from_secs(30),
+            outbound_queue_capacity: 64,
+            overflow_policy: OverflowPolicy::CoalesceState,
+            admin_token: None,
+            max_message_size: 4 * 1024,
+            connection_action_limit: RateLimit { burst: 20, per_second: 10.0 },
+            ip_action_limit: RateLimit { burst: 60, per_second: 30.0 },
+            ip_join_limit: RateLimit { burst: 10, per_second: 1.0 },
+            max_rate_limit_violations: 5,
+            auth_secret: None,
+            token_ttl: Duration::from_secs(24 * 60 * 60),
+            require_auth: false,
+            allowed_origins: None,
+            #[cfg(feature = "tls")]
+            tls: None,
         }
-        None
     }
 }
 
 pub struct AppState {
-    pub rooms: HashMap<String, Room>,
+    // Each room is owned by its own actor task; the map only holds the command channel handles
+    pub rooms: DashMap<String, RoomHandle>,
     // Counter to assign unique connection ids
-    pub next_connection_id: ConnectionId,
+    pub next_connection_id: AtomicU64,
+    pub config: ServerConfig,
+    pub metrics: Metrics,
+    // Drain mode: no new rooms are created, existing games play out
+    pub draining: AtomicBool,
+    pub ip_action_limiter: IpRateLimiter,
+    pub ip_join_limiter: IpRateLimiter,
+    pub tokens: TokenSigner,
+}
+
+impl AppState {
+    pub fn new(config: ServerConfig) -> Self {
+        Self {
+            rooms: DashMap::new(),
+            next_connection_id: AtomicU64::new(0),
+            ip_action_limiter: IpRateLimiter::new(config.ip_action_limit),
+            ip_join_limiter: IpRateLimiter::new(config.ip_join_limit),
+            tokens: TokenSigner::new(config.auth_secret.as_deref(), config.token_ttl),
+            config,
+            metrics: Metrics::new(),
+            draining: AtomicBool::new(false),
+        }
+    }
+
+    pub fn next_connection_id(&self) -> ConnectionId {
+        self.next_connection_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    // Rooms with their player counts, without waiting on the room actors.
+    pub fn room_summaries(&self) -> Vec<RoomSummary> {
+        self.rooms.iter()
+            .map(|entry| RoomSummary { room_id: entry.key().clone(), players: entry.value().players() })
+            .collect()
+    }
+}
+
+pub type SharedState = Arc<AppState>;
+
+/// Players per room.
+pub const ROOM_CAPACITY: usize = 2;
+
+/// A room as listed for the LAN lobby.
+#[derive(Clone, Debug)]
+pub struct RoomSummary {
+    pub room_id: String,
+    pub players: usize,
+}
+
+const RATE_LIMIT_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+// How long in-flight TLS requests get to finish on shutdown
+#[cfg(feature = "tls")]
+const TLS_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+// Liveness: the process is up and serving HTTP.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+// Readiness: accepting new rooms. Load balancers should stop routing new players here while draining.
+async fn readyz(State(state): State<SharedState>) -> (StatusCode, &'static str) {
+    if state.is_draining() { (StatusCode::SERVICE_UNAVAILABLE, "draining") } else { (StatusCode::OK, "ready") }
+}
+
+pub async fn start_server() -> io::Result<()> {
+    start_server_with_config(ServerConfig::default()).await
+}
+
+pub async fn start_server_with_config(config: ServerConfig) -> io::Result<()> {
+    start_server_with_state(Arc::new(AppState::new(config))).await
+}
+
+pub async fn start_server_with_state(state: SharedState) -> io::Result<()> {
+    let listener = bind(state.config.port).await?;
+    serve_state(listener, state).await
 }
 
-pub type SharedState = Arc<Mutex<AppState>>;
+// Bind every interface on `port`, e.g. to report a port already in use before serving.
+pub async fn bind(port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port))).await
+}
 
-pub async fn start_server() {
-    let state: SharedState = Arc::new(Mutex::new(AppState {
-        rooms: HashMap::new(),
-        next_connection_id: 0,
-    }));
+// Serve on an already bound listener, e.g. one bound to port 0 by a benchmark.
+pub async fn serve(listener: TcpListener, config: ServerConfig) -> io::Result<()> {
+    serve_state(listener, Arc::new(AppState::new(config))).await
+}
+
+// Like `serve`, with a state the caller keeps a handle to, e.g. to advertise the server's rooms on the LAN.
+pub async fn serve_state(listener: TcpListener, state: SharedState) -> io::Result<()> {
+    serve_state_until(listener, state, std::future::pending()).await
+}
 
-    let app = Router::new()
+/// Like `serve_state`, until `shutdown` resolves. Then every room is closed with a notice to its players, new
+/// joins are refused, and the listener stops accepting once in-flight HTTP requests have finished.
+pub async fn serve_state_until<F>(listener: TcpListener, state: SharedState, shutdown: F) -> io::Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    info!(addr = ?listener.local_addr().ok(), "tic tac toe server listening");
+
+    let mut app = Router::new()
         .route("/join/{room_id}", get(room::join_room))
-        .with_state(state);
-    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        .route("/protocol/schema", get(room::protocol_schema))
+        .route("/auth/guest", post(auth::guest_login))
+        .route("/metrics", get(metrics::metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz));
+    if state.config.admin_token.is_some() {
+        app = app.nest("/admin", room::admin_routes(state.clone()));
+    }
+    let app = app
+        .layer(middleware::from_fn_with_state(state.clone(), cors::origin_policy))
+        .with_state(state.clone());
+    #[cfg(feature = "tls")]
+    let tls_config = state.config.tls.clone();
+
+    // Fires once `shutdown` resolved and every room was told to close
+    let (stopping_tx, stopping) = watch::channel(false);
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            shutdown.await;
+            close_all_rooms(&state).await;
+            let _ = stopping_tx.send(true);
+        });
+    }
 
-    axum::serve(listener, app).await.unwrap();
+    // Keep the per-IP limiter maps from growing with every address ever seen
+    {
+        let state = state.clone();
+        let mut stopping = stopping.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(RATE_LIMIT_PRUNE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        state.ip_action_limiter.prune();
+                        state.ip_join_limiter.prune();
+                    }
+                    _ = stopping.wait_for(|&stopping| stopping) => break,
+                }
+            }
+        });
+    }
+
+    // Connect info gives handlers the client address for per-IP rate limits
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    #[cfg(feature = "tls")]
+    if let Some(tls_config) = tls_config {
+        let rustls = tls::rustls_config(&tls_config).await?;
+        let handle = axum_server::Handle::new();
+        let shutdown_handle = handle.clone();
+        let mut stopping = stopping.clone();
+        tokio::spawn(async move {
+            if stopping.wait_for(|&stopping| stopping).await.is_ok() {
+                shutdown_handle.graceful_shutdown(Some(TLS_SHUTDOWN_GRACE));
+            }
+        });
+        axum_server::from_tcp_rustls(listener.into_std()?, rustls).handle(handle).serve(app).await?;
+        info!("tic tac toe server stopped");
+        return Ok(());
+    }
+    let mut stopping = stopping.clone();
+    let stopped = async move {
+        // An error means the shutdown task is gone without firing, i.e. never stop
+        if stopping.wait_for(|&stopping| stopping).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    axum::serve(listener, app).with_graceful_shutdown(stopped).await?;
+    info!("tic tac toe server stopped");
+    Ok(())
+}
+
+// Drain, then disconnect everyone. Rooms shut down on their own once empty.
+async fn close_all_rooms(state: &SharedState) {
+    state.draining.store(true, Ordering::Relaxed);
+    let handles: Vec<RoomHandle> = state.rooms.iter().map(|entry| entry.value().clone()).collect();
+    info!(rooms = handles.len(), "server shutting down");
+    for handle in handles {
+        handle.send(RoomCommand::Close { reason: "Server is shutting down".to_string() }).await;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,58 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::info;
+
+/// Serve `wss://` with a PEM certificate chain and private key.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    // When set and the files don't exist yet, a self-signed certificate for these host names / IPs is
+    // generated and saved there first (LAN hosting); otherwise both files must already exist
+    pub self_signed_for: Option<Vec<String>>,
+}
+
+/// Generate a self-signed certificate for `subject_alt_names` (host names or IP addresses), returned as
+/// `(certificate_pem, private_key_pem)`.
+pub fn generate_self_signed(subject_alt_names: Vec<String>) -> Result<(String, String), rcgen::Error> {
+    let certified = rcgen::generate_simple_self_signed(subject_alt_names)?;
+    Ok((certified.cert.pem(), certified.key_pair.serialize_pem()))
+}
+
+// The private key is only readable by the owner. `mode` only applies to new files, so an existing one is
+// tightened as well before the key goes in.
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::fs::{OpenOptions, Permissions};
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+// Generate and save a self-signed certificate unless both files are already there, so clients that trusted
+// it once keep trusting it across restarts.
+fn ensure_self_signed(cert_path: &Path, key_path: &Path, names: &[String]) -> io::Result<()> {
+    if cert_path.exists() && key_path.exists() {
+        return Ok(());
+    }
+    let (cert_pem, key_pem) = generate_self_signed(names.to_vec()).map_err(io::Error::other)?;
+    std::fs::write(cert_path, cert_pem)?;
+    write_private(key_path, key_pem.as_bytes())?;
+    info!(cert = %cert_path.display(), ?names, "generated self-signed certificate");
+    Ok(())
+}
+
+pub async fn rustls_config(tls: &TlsConfig) -> io::Result<RustlsConfig> {
+    if let Some(names) = &tls.self_signed_for {
+        ensure_self_signed(&tls.cert_path, &tls.key_path, names)?;
+    }
+    RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path).await
+}